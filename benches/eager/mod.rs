@@ -0,0 +1,300 @@
+//! The Borrow Tree as it was before lazy revocation, kept as the baseline
+//! of the benchmark: every access walks the whole tree, and revoking a
+//! subtree visits every node in it.
+
+use capslock_lite::model::{AliasingModel, Fault, ROOT};
+use capslock_lite::runtime::{Access, Perm};
+
+/// Per-node permission state, with the transitions of `BorrowTree`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// A fresh `&mut` that has not written yet (two-phase borrow).
+    /// Tolerates foreign reads, so the owner may still read through it.
+    Reserved,
+    /// A `&mut` (or owner) that has written. A foreign read freezes it.
+    Active,
+    /// Read-only: a `&T`, or a `&mut T` that saw a foreign read.
+    Frozen,
+    /// Shared and writable (`Perm::SharedReadWrite`, e.g. `&Cell<T>`).
+    /// Unaffected by foreign accesses.
+    Cell,
+    /// Revoked; any further local access is a violation.
+    Disabled,
+}
+
+impl State {
+    /// The state a freshly derived pointer starts in.
+    fn initial(perm: Perm) -> Self {
+        match perm {
+            Perm::Shared => State::Frozen,
+            Perm::SharedReadWrite => State::Cell,
+            Perm::Mutable => State::Reserved,
+        }
+    }
+
+    /// Applies an access to this state. Returns None if the access is a violation.
+    /// `interior` is set when the accessed location is interior-mutable.
+    fn transition(self, access: Access, local: bool, interior: bool) -> Option<Self> {
+        match (self, access, local) {
+            (State::Cell, _, _) => Some(State::Cell),
+            (State::Frozen, _, _) if interior => Some(State::Frozen),
+            (State::Disabled, _, true) => None,
+            (State::Frozen, Access::Write, true) => None,
+            (State::Reserved, Access::Write, true) => Some(State::Active),
+            (state, Access::Read, true) | (state, Access::Write, true) => Some(state),
+            (_, Access::Write, false) => Some(State::Disabled),
+            (State::Active, Access::Read, false) => Some(State::Frozen),
+            (state, Access::Read, false) => Some(state),
+        }
+    }
+}
+
+/// A node in the Borrow Tree representing a specific pointer derivation.
+/// Tracks lineage (parent/children) and current permission state.
+#[derive(Debug, Clone)]
+struct Node {
+    id: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    permission: Perm,
+    state: State,
+    /// Number of active protectors (calls the pointer was passed into).
+    protectors: u32,
+}
+
+/// Eager Tree Borrows for one allocation. Disabled nodes are unlinked from
+/// their parent's children, but keep their slot while the allocation is live.
+pub struct EagerTree {
+    nodes: Vec<Node>,
+}
+
+impl Default for EagerTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EagerTree {
+    /// A tree holding only the allocation's owner.
+    /// The root is implicitly Mutable and starts Active.
+    pub fn new() -> Self {
+        let root = Node {
+            id: ROOT,
+            parent: None,
+            children: Vec::new(),
+            permission: Perm::Mutable,
+            state: State::Active,
+            protectors: 0,
+        };
+        Self { nodes: vec![root] }
+    }
+
+    /// Derives a child pointer from a valid parent.
+    /// Returns None if the parent is already invalid.
+    fn spawn_child(&mut self, parent_id: usize, perm: Perm) -> Option<usize> {
+        if parent_id >= self.nodes.len() || self.nodes[parent_id].state == State::Disabled {
+            return None;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            id,
+            parent: Some(parent_id),
+            children: Vec::new(),
+            permission: perm,
+            state: State::initial(perm),
+            protectors: 0,
+        });
+        self.nodes[parent_id].children.push(id);
+        Some(id)
+    }
+
+    /// Unlinks a Disabled node from its parent.
+    fn detach(&mut self, id: usize) {
+        if let Some(parent) = self.nodes[id].parent {
+            self.nodes[parent].children.retain(|&child| child != id);
+        }
+    }
+
+    /// Recursively invalidates a node and its entire subtree (descendants).
+    /// Uses an iterative stack approach to prevent stack overflow on deep trees.
+    fn deep_revoke(&mut self, id: usize) {
+        if id >= self.nodes.len() { return; }
+        let mut stack = vec![id];
+        while let Some(curr) = stack.pop() {
+            if let Some(node) = self.nodes.get_mut(curr) {
+                if node.state != State::Disabled {
+                    node.state = State::Disabled;
+                    stack.extend_from_slice(&node.children);
+                }
+            }
+        }
+    }
+
+    /// Ends the borrow `id` and everything derived from it: the subtree is
+    /// Disabled and detached, so later accesses no longer visit it.
+    /// Fails, without modifying anything, on a protected node.
+    fn release(&mut self, id: usize) -> Result<(), Fault> {
+        if id >= self.nodes.len() { return Ok(()); }
+        let mut stack = vec![id];
+        while let Some(curr) = stack.pop() {
+            let node = &self.nodes[curr];
+            if node.protectors > 0 {
+                return Err(Fault::Protected(curr));
+            }
+            stack.extend_from_slice(&node.children);
+        }
+
+        self.deep_revoke(id);
+        self.detach(id);
+        Ok(())
+    }
+
+    /// Applies an access through `id` to the whole tree of its allocation.
+    /// On a violation nothing is modified.
+    fn access(&mut self, id: usize, access: Access, interior: bool) -> Result<(), Fault> {
+        let path = self.lineage(id);
+
+        // 1. Local transitions (the node and its ancestors). Validate before mutating.
+        for &idx in &path {
+            if self.nodes[idx].state.transition(access, true, interior).is_none() {
+                return Err(self.fault(idx));
+            }
+        }
+
+        // 2. Foreign transitions (everything else in the allocation), collected
+        // first so a protected node can still reject the access.
+        // Nodes below a Disabled node can never be used again (their local
+        // path is Disabled), so those subtrees are skipped.
+        let mut foreign = Vec::new();
+        let mut stack = vec![ROOT];
+        while let Some(curr) = stack.pop() {
+            let node = &self.nodes[curr];
+            if !path.contains(&curr) {
+                if node.state == State::Disabled {
+                    continue;
+                }
+                let next = node.state.transition(access, false, interior).unwrap_or(State::Disabled);
+                if next == State::Disabled && node.protectors > 0 {
+                    return Err(Fault::Protected(curr));
+                }
+                foreign.push((curr, next));
+            }
+            stack.extend_from_slice(&node.children);
+        }
+
+        for &idx in &path {
+            let node = &mut self.nodes[idx];
+            node.state = node.state.transition(access, true, interior).unwrap_or(State::Disabled);
+        }
+        for (idx, next) in foreign {
+            self.nodes[idx].state = next;
+            if next == State::Disabled {
+                self.detach(idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps the node that rejected an access to the reason.
+    fn fault(&self, id: usize) -> Fault {
+        match self.nodes[id].state {
+            // A Frozen node on the path only rejects writes.
+            State::Frozen => Fault::ReadOnly,
+            _ => Fault::Revoked,
+        }
+    }
+
+    /// Collects a node and all its ancestors, innermost first.
+    fn lineage(&self, id: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            match self.nodes.get(idx) {
+                Some(node) => {
+                    path.push(node.id);
+                    curr = node.parent;
+                }
+                None => break,
+            }
+        }
+        path
+    }
+
+    /// Nodes that can still be used: reachable from the root without
+    /// crossing a Disabled node.
+    fn live_nodes(&self) -> Vec<usize> {
+        let mut live = Vec::new();
+        let mut stack = vec![ROOT];
+        while let Some(curr) = stack.pop() {
+            let node = &self.nodes[curr];
+            if node.state != State::Disabled {
+                live.push(curr);
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        live
+    }
+}
+
+impl AliasingModel for EagerTree {
+    fn name(&self) -> &'static str {
+        "Tree Borrows (eager)"
+    }
+
+    /// Retagging reads through the parent (Tree Borrows' implicit read on reborrow).
+    fn on_retag(&mut self, parent: usize, perm: Perm) -> Result<usize, Fault> {
+        self.access(parent, Access::Read, false)?;
+        self.spawn_child(parent, perm).ok_or(Fault::Revoked)
+    }
+
+    fn on_read(&mut self, id: usize) -> Result<(), Fault> {
+        self.access(id, Access::Read, false)
+    }
+
+    fn on_write(&mut self, id: usize, interior: bool) -> Result<(), Fault> {
+        self.access(id, Access::Write, interior)
+    }
+
+    fn on_revoke(&mut self) {
+        self.deep_revoke(ROOT);
+    }
+
+    fn on_release(&mut self, id: usize) -> Result<(), Fault> {
+        self.release(id)
+    }
+
+    fn protect(&mut self, id: usize) -> Result<(), Fault> {
+        let path = self.lineage(id);
+        if path.is_empty() || path.iter().any(|&idx| self.nodes[idx].state == State::Disabled) {
+            return Err(Fault::Revoked);
+        }
+        self.nodes[id].protectors += 1;
+        Ok(())
+    }
+
+    fn unprotect(&mut self, id: usize) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.protectors = node.protectors.saturating_sub(1);
+        }
+    }
+
+    fn protected(&self) -> Option<usize> {
+        self.nodes.iter().position(|node| node.protectors > 0 && node.state != State::Disabled)
+    }
+
+    fn perm(&self, id: usize) -> Option<Perm> {
+        self.nodes.get(id).map(|node| node.permission)
+    }
+
+    fn derived(&self, id: usize) -> bool {
+        id < self.nodes.len()
+    }
+
+    fn lineage(&self, id: usize) -> Vec<usize> {
+        EagerTree::lineage(self, id)
+    }
+
+    fn live_nodes(&self) -> Vec<usize> {
+        EagerTree::live_nodes(self)
+    }
+}
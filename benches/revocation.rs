@@ -0,0 +1,202 @@
+//! Access-path benchmark for the aliasing models and shadow backends.
+//! Run with `cargo bench --bench revocation`; prints ns and heap
+//! allocations per checked operation. Tree Borrows is also measured with
+//! eager revocation (`eager`), the baseline the lazy tree replaced.
+
+mod eager;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use capslock_lite::model::{AliasingModel, Model};
+use capslock_lite::runtime::{Perm, Runtime, Tag};
+use capslock_lite::shadow::Shadow;
+
+use eager::EagerTree;
+
+const BASE: usize = 0x10_000;
+
+/// Counts heap allocations, so the access paths can be checked allocation-free.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A workload returns the number of checked operations it performed.
+type Workload = fn(&mut Runtime) -> usize;
+
+/// Repeated reads and writes through one `&mut` (a hot loop over a buffer).
+fn hot_loop(rt: &mut Runtime) -> usize {
+    let owner = rt.handle_alloc(BASE, 64);
+    let child = rt.handle_reborrow(BASE, owner, Perm::Mutable).unwrap();
+    for _ in 0..100_000 {
+        black_box(rt.handle_write(BASE, 8, child)).unwrap();
+        black_box(rt.handle_read(BASE, 8, child)).unwrap();
+    }
+    200_000
+}
+
+/// Derives a chain of `depth` `&mut`, each written once, and returns its tip.
+fn chain(rt: &mut Runtime, depth: usize) -> Tag {
+    let mut tag = rt.handle_alloc(BASE, 64);
+    for _ in 0..depth {
+        tag = rt.handle_reborrow(BASE, tag, Perm::Mutable).unwrap();
+        black_box(rt.handle_write(BASE, 8, tag)).unwrap();
+    }
+    tag
+}
+
+/// Accesses through the tip of a deep reborrow chain (recursive descent).
+fn deep_chain(rt: &mut Runtime) -> usize {
+    let tag = chain(rt, 1_000);
+    for _ in 0..10_000 {
+        black_box(rt.handle_write(BASE, 8, tag)).unwrap();
+    }
+    11_000
+}
+
+/// Writes alternating between two `&Cell` siblings at the tip of a chain
+/// `DEPTH` deep, so each write is foreign to the previous writer.
+fn deep_writers<const DEPTH: usize>(rt: &mut Runtime) -> usize {
+    let tip = chain(rt, DEPTH);
+    let left = rt.handle_reborrow(BASE, tip, Perm::SharedReadWrite).unwrap();
+    let right = rt.handle_reborrow(BASE, tip, Perm::SharedReadWrite).unwrap();
+    for _ in 0..10_000 {
+        black_box(rt.handle_write(BASE, 8, left)).unwrap();
+        black_box(rt.handle_write(BASE, 8, right)).unwrap();
+    }
+    DEPTH + 20_000
+}
+
+/// A short-lived `&mut` at the tip of a chain `DEPTH` deep is derived,
+/// released, and followed by a read through the tip.
+fn deep_release<const DEPTH: usize>(rt: &mut Runtime) -> usize {
+    let tip = chain(rt, DEPTH);
+    for _ in 0..10_000 {
+        let child = rt.handle_reborrow(BASE, tip, Perm::Mutable).unwrap();
+        black_box(rt.handle_release(BASE, child)).unwrap();
+        black_box(rt.handle_read(BASE, 8, tip)).unwrap();
+    }
+    DEPTH + 30_000
+}
+
+/// Many `&T` siblings read round-robin (shared iteration).
+fn wide_siblings(rt: &mut Runtime) -> usize {
+    let owner = rt.handle_alloc(BASE, 64);
+    let readers: Vec<Tag> = (0..1_000)
+        .map(|_| rt.handle_reborrow(BASE, owner, Perm::Shared).unwrap())
+        .collect();
+    for _ in 0..20 {
+        for &reader in &readers {
+            black_box(rt.handle_read(BASE, 8, reader)).unwrap();
+        }
+    }
+    21_000
+}
+
+/// A `&mut` derives a chain of borrows, then the owner writes and revokes them all.
+fn revoke_churn(rt: &mut Runtime) -> usize {
+    let owner = rt.handle_alloc(BASE, 64);
+    for _ in 0..1_000 {
+        let mut tag = rt.handle_reborrow(BASE, owner, Perm::Mutable).unwrap();
+        for _ in 0..50 {
+            tag = rt.handle_reborrow(BASE, tag, Perm::Mutable).unwrap();
+        }
+        black_box(rt.handle_write(BASE, 8, owner)).unwrap();
+    }
+    52_000
+}
+
+/// Reads through the owners of many small allocations in turn, so the cost
+/// is dominated by resolving addresses.
+fn scattered(rt: &mut Runtime) -> usize {
+    let owners: Vec<(usize, Tag)> = (0..4_096)
+        .map(|i| {
+            let addr = BASE + i * 4_096;
+            (addr, rt.handle_alloc(addr, 64))
+        })
+        .collect();
+    for _ in 0..20 {
+        for &(addr, owner) in &owners {
+            black_box(rt.handle_read(addr + 8, 8, owner)).unwrap();
+        }
+    }
+    4_096 * 21
+}
+
+/// Runs `workload` once and prints ns and allocations per operation.
+fn measure(mut rt: Runtime, workload: Workload) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let ops = workload(&mut rt);
+    let ns = start.elapsed().as_nanos() as f64 / ops as f64;
+    let allocs = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64 / ops as f64;
+    print!(" {:>10.1} ns {:>7.4} alloc", ns, allocs);
+}
+
+fn main() {
+    let workloads: [(&str, Workload); 5] = [
+        ("hot_loop", hot_loop),
+        ("deep_chain", deep_chain),
+        ("wide_siblings", wide_siblings),
+        ("revoke_churn", revoke_churn),
+        ("scattered", scattered),
+    ];
+
+    let eager: fn() -> Box<dyn AliasingModel> = || Box::new(EagerTree::new());
+    let eager_name = EagerTree::new().name();
+    println!("{:<16}{:>28}{:>28}{:>28}", "workload", eager_name, "Tree Borrows", "Stacked Borrows");
+    for (name, workload) in workloads {
+        print!("{:<16}", name);
+        measure(Runtime::with_custom_model(eager_name, eager, Shadow::Ranges), workload);
+        for model in [Model::TreeBorrows, Model::StackedBorrows] {
+            measure(Runtime::with_model(model), workload);
+        }
+        println!();
+    }
+
+    // Too slow for the eager baseline, which walks the whole tree per write.
+    let deep: [(&str, Workload); 4] = [
+        ("deep_writers/1k", deep_writers::<1_000>),
+        ("deep_writers/10k", deep_writers::<10_000>),
+        ("deep_release/1k", deep_release::<1_000>),
+        ("deep_release/10k", deep_release::<10_000>),
+    ];
+    println!("\n{:<16}{:>28}{:>28}", "deep chains", "Tree Borrows", "Stacked Borrows");
+    for (name, workload) in deep {
+        print!("{:<16}", name);
+        for model in [Model::TreeBorrows, Model::StackedBorrows] {
+            measure(Runtime::with_model(model), workload);
+        }
+        println!();
+    }
+
+    println!("\n{:<16}{:>28}{:>28}", "Tree Borrows", "ranges shadow", "flat shadow");
+    for (name, workload) in workloads {
+        print!("{:<16}", name);
+        for shadow in [Shadow::Ranges, Shadow::Flat] {
+            measure(Runtime::with_shadow(Model::TreeBorrows, shadow), workload);
+        }
+        println!();
+    }
+}
@@ -1,9 +1,11 @@
 fn main() {
     // Tell Cargo to re-run this script if the C file changes
     println!("cargo:rerun-if-changed=src/bad_actor.c");
+    println!("cargo:rerun-if-changed=include/capslock.h");
 
     // Compile the C code into a static library
     cc::Build::new()
         .file("src/bad_actor.c")
+        .include("include")
         .compile("bad_actor");
 }
\ No newline at end of file
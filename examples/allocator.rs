@@ -0,0 +1,27 @@
+//! Installs `CapslockAllocator`, so every heap allocation is tracked without
+//! calling `track_alloc`. Run with `cargo run --example allocator`.
+
+use std::alloc::System;
+
+use capslock_lite::alloc::CapslockAllocator;
+use capslock_lite::runtime::{check_read, owner_tag, set_scope, try_check_read, Scope};
+
+#[global_allocator]
+static ALLOC: CapslockAllocator<System> = CapslockAllocator::new(System);
+
+fn main() {
+    // The allocator registers into the process-wide runtime.
+    set_scope(Scope::Process);
+
+    let numbers = vec![1, 2, 3];
+    let ptr = numbers.as_ptr();
+    let tag = owner_tag(ptr).expect("the allocator registered the Vec's buffer");
+    check_read(ptr, tag);
+    println!("Vec buffer at {:p} is tracked as {:?}", ptr, tag);
+
+    drop(numbers);
+    match try_check_read(ptr, tag) {
+        Err(violation) => println!("Caught after drop: {}", violation),
+        Ok(()) => println!("FAILURE: the dropped buffer was still accepted"),
+    }
+}
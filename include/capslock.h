@@ -0,0 +1,115 @@
+/*
+ * capslock.h - C API for the CapsLock-lite runtime monitor.
+ *
+ * Foreign code uses these entry points to register its own pointers and
+ * check accesses against the same Runtime that tracks the Rust side.
+ * No function ever unwinds; failures are reported through capslock_status.
+ */
+#ifndef CAPSLOCK_H
+#define CAPSLOCK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Permission of a derived pointer (mirrors runtime::Perm). */
+typedef enum capslock_perm {
+    CAPSLOCK_SHARED = 0,            /* Read-only, may coexist with other shared pointers */
+    CAPSLOCK_MUTABLE = 1,           /* Unique, must be exclusive */
+    CAPSLOCK_SHARED_READ_WRITE = 2  /* Shared and writable (interior mutability) */
+} capslock_perm;
+
+/* Opaque handle identifying one tracked pointer (mirrors runtime::Tag).
+ * It encodes the allocation it was derived from, so a tag from a previous
+ * allocation at a reused address is rejected. Passed by value. */
+typedef struct capslock_tag {
+    uint64_t alloc_id;
+    uint64_t id;
+} capslock_tag;
+
+/* Result of every call (mirrors ffi::Status). */
+typedef enum capslock_status {
+    CAPSLOCK_OK = 0,
+    CAPSLOCK_VIOLATION = -1,        /* Aliasing or provenance violation */
+    CAPSLOCK_BUSY = -2,             /* Runtime unavailable (re-entrancy, teardown) */
+    CAPSLOCK_INVALID_ARGUMENT = -3  /* e.g. unknown capslock_perm value */
+} capslock_status;
+
+/* What the runtime does on a violation (mirrors violation::Policy).
+ * The default comes from the CAPSLOCK_MODE environment variable
+ * (panic, abort, log, count). It applies to every thread, whatever the
+ * scope. Calls made from C never unwind: under
+ * CAPSLOCK_PANIC they simply return CAPSLOCK_VIOLATION. */
+typedef enum capslock_policy {
+    CAPSLOCK_PANIC = 0,
+    CAPSLOCK_ABORT = 1,
+    CAPSLOCK_LOG = 2,
+    CAPSLOCK_COUNT = 3
+} capslock_policy;
+
+/* Which runtime the calls operate on (mirrors runtime::Scope). The default
+ * comes from the CAPSLOCK_SCOPE environment variable (thread, process).
+ * Select it before tracking anything: the two runtimes share no state. */
+typedef enum capslock_scope {
+    CAPSLOCK_THREAD = 0,  /* One runtime per thread */
+    CAPSLOCK_PROCESS = 1  /* One runtime shared by all threads */
+} capslock_scope;
+
+/* Registers a new allocation of size bytes starting at base; the owner's tag
+ * is stored in out_tag. Interior pointers resolve to this allocation. */
+capslock_status capslock_alloc(uintptr_t base, size_t size, capslock_tag *out_tag);
+
+/* Registers a reborrow of (parent, parent_tag); the new tag is stored in out_tag. */
+capslock_status capslock_borrow(uintptr_t parent, capslock_tag parent_tag, int perm,
+                                capslock_tag *out_tag);
+
+/* Validates a read / write of len bytes through addr using tag. An access
+ * that leaves the allocation tag was derived from is a violation. */
+capslock_status capslock_check_read(uintptr_t addr, size_t len, capslock_tag tag);
+capslock_status capslock_check_write(uintptr_t addr, size_t len, capslock_tag tag);
+
+/* Ends the borrow (addr, tag) and every pointer derived from it. Later use of
+ * their tags is a violation; releasing an already revoked tag is not. */
+capslock_status capslock_release(uintptr_t addr, capslock_tag tag);
+
+/* Protects (addr, tag) until capslock_unprotect(tag): any access that would
+ * invalidate it in the meantime is a violation. Protectors nest. */
+capslock_status capslock_protect(uintptr_t addr, capslock_tag tag);
+capslock_status capslock_unprotect(capslock_tag tag);
+
+/* Marks size bytes at addr as interior-mutable: writes there through shared
+ * pointers are allowed and do not invalidate sibling readers. */
+capslock_status capslock_mark_interior_mut(uintptr_t addr, size_t size);
+
+/* Tracks a realloc of the allocation starting at old to new_size bytes at new;
+ * the owner's tag of the result is stored in out_tag. Resized in place, the
+ * allocation keeps its tags; moved, every tag derived from old is revoked. */
+capslock_status capslock_realloc(uintptr_t old, uintptr_t new_base, size_t new_size, capslock_tag *out_tag);
+
+/* Releases the allocation starting at base, revoking every derived pointer.
+ * Later use of its tags is a use-after-free, even if the address is reused;
+ * releasing it twice is a double free, and releasing an address inside it
+ * rather than base is an invalid free. */
+capslock_status capslock_free(uintptr_t base);
+
+/* Deep-revokes the allocation containing base. While a pointer into it is
+ * protected, the violation is only handed to the policy and nothing is revoked. */
+void capslock_revoke(uintptr_t base);
+
+/* Selects the violation policy of every thread; returns CAPSLOCK_INVALID_ARGUMENT for unknown values. */
+capslock_status capslock_set_policy(int policy);
+
+/* Number of violations reported so far. */
+size_t capslock_violation_count(void);
+
+/* Selects the runtime scope; returns CAPSLOCK_INVALID_ARGUMENT for unknown values. */
+capslock_status capslock_set_scope(int scope);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CAPSLOCK_H */
@@ -0,0 +1,218 @@
+//! `LD_PRELOAD` shim: interposes the C allocator, so the heap objects of
+//! unmodified C programs and libraries are tracked by a CapsLock runtime
+//! inside the shim.
+//!
+//! ```sh
+//! cargo build -p capslock_preload
+//! LD_PRELOAD=target/debug/libcapslock_preload.so ./program
+//! ```
+//!
+//! Every call goes through `CapslockAllocator`, so the runtime's own
+//! allocations, which reach these same symbols, are forwarded untracked.
+//! The library carries its own runtime; C code reaches it through the
+//! `capslock_*` functions it exports. A Rust program that links
+//! `capslock_lite` itself keeps a separate runtime, which never sees these
+//! blocks: under the shim, its `owner_tag` on a `malloc` block is None.
+//! Loading it selects the process-wide
+//! runtime (`Scope::Process`), whatever `CAPSLOCK_SCOPE` says, since blocks
+//! are routinely freed by other threads than the one that allocated them.
+//! Requires glibc.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::os::raw::{c_int, c_long, c_void};
+use std::ptr;
+
+use capslock_lite::alloc::CapslockAllocator;
+use capslock_lite::runtime::{set_scope, Scope};
+
+extern "C" {
+    // glibc's allocator under names that are never interposed.
+    fn __libc_malloc(size: usize) -> *mut c_void;
+    fn __libc_calloc(count: usize, size: usize) -> *mut c_void;
+    fn __libc_realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn __libc_free(ptr: *mut c_void);
+    fn __libc_memalign(align: usize, size: usize) -> *mut c_void;
+
+    fn __errno_location() -> *mut c_int;
+    fn sysconf(name: c_int) -> c_long;
+}
+
+const ENOMEM: c_int = 12;
+const EINVAL: c_int = 22;
+const SC_PAGESIZE: c_int = 30;
+
+/// The alignment `malloc` guarantees on 64-bit glibc.
+const MALLOC_ALIGN: usize = 16;
+
+/// glibc's allocator. C callers never pass a layout back, so `dealloc` and
+/// `realloc` ignore it.
+struct Libc;
+
+unsafe impl GlobalAlloc for Libc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() <= MALLOC_ALIGN {
+            __libc_malloc(layout.size()).cast()
+        } else {
+            __libc_memalign(layout.align(), layout.size()).cast()
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if layout.align() <= MALLOC_ALIGN {
+            return __libc_calloc(1, layout.size()).cast();
+        }
+        let ptr = self.alloc(layout);
+        if !ptr.is_null() {
+            ptr::write_bytes(ptr, 0, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        __libc_free(ptr.cast())
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, _layout: Layout, new_size: usize) -> *mut u8 {
+        __libc_realloc(ptr.cast(), new_size).cast()
+    }
+}
+
+static ALLOC: CapslockAllocator<Libc> = CapslockAllocator::new(Libc);
+
+/// Runs when the library is loaded, before the program's first call.
+extern "C" fn select_process_scope() {
+    set_scope(Scope::Process);
+}
+
+#[used]
+#[link_section = ".init_array"]
+static SELECT_PROCESS_SCOPE: extern "C" fn() = select_process_scope;
+
+/// Stands in for the unknown layout of a block C hands back.
+const UNKNOWN: Layout = Layout::new::<u8>();
+
+/// # Safety
+/// Same contract as C `malloc`.
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    match Layout::from_size_align(size, MALLOC_ALIGN) {
+        Ok(layout) => ALLOC.alloc(layout).cast(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// Same contract as C `calloc`.
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size).map(|size| Layout::from_size_align(size, MALLOC_ALIGN)) {
+        Some(Ok(layout)) => ALLOC.alloc_zeroed(layout).cast(),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// Same contract as C `realloc`.
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+    if size == 0 {
+        free(ptr);
+        return ptr::null_mut();
+    }
+    ALLOC.realloc(ptr.cast(), UNKNOWN, size).cast()
+}
+
+/// # Safety
+/// Same contract as C `free`.
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if !ptr.is_null() {
+        ALLOC.dealloc(ptr.cast(), UNKNOWN);
+    }
+}
+
+/// # Safety
+/// Same contract as C `posix_memalign`.
+#[no_mangle]
+pub unsafe extern "C" fn posix_memalign(memptr: *mut *mut c_void, align: usize, size: usize) -> c_int {
+    if !align.is_power_of_two() || !align.is_multiple_of(std::mem::size_of::<*mut c_void>()) {
+        return EINVAL;
+    }
+    let ptr = match Layout::from_size_align(size, align) {
+        Ok(layout) => ALLOC.alloc(layout),
+        Err(_) => ptr::null_mut(),
+    };
+    if ptr.is_null() {
+        return ENOMEM;
+    }
+    *memptr = ptr.cast();
+    0
+}
+
+/// A block of `size` bytes aligned to `align`, a power of two; null with
+/// `errno` set otherwise. Alignments below `malloc`'s are raised to it.
+unsafe fn aligned(align: usize, size: usize) -> *mut c_void {
+    if !align.is_power_of_two() {
+        *__errno_location() = EINVAL;
+        return ptr::null_mut();
+    }
+    match Layout::from_size_align(size, align.max(MALLOC_ALIGN)) {
+        Ok(layout) => ALLOC.alloc(layout).cast(),
+        Err(_) => {
+            *__errno_location() = ENOMEM;
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// Same contract as C `aligned_alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn aligned_alloc(align: usize, size: usize) -> *mut c_void {
+    aligned(align, size)
+}
+
+/// # Safety
+/// Same contract as C `memalign`. Like glibc, rounds `align` up to a
+/// power of two.
+#[no_mangle]
+pub unsafe extern "C" fn memalign(align: usize, size: usize) -> *mut c_void {
+    aligned(align.checked_next_power_of_two().unwrap_or(0), size)
+}
+
+/// # Safety
+/// Same contract as C `valloc`.
+#[no_mangle]
+pub unsafe extern "C" fn valloc(size: usize) -> *mut c_void {
+    aligned(sysconf(SC_PAGESIZE) as usize, size)
+}
+
+/// # Safety
+/// Same contract as C `pvalloc`: rounds `size` up to whole pages.
+#[no_mangle]
+pub unsafe extern "C" fn pvalloc(size: usize) -> *mut c_void {
+    let page = sysconf(SC_PAGESIZE) as usize;
+    match size.max(1).checked_next_multiple_of(page) {
+        Some(size) => aligned(page, size),
+        None => {
+            *__errno_location() = ENOMEM;
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// Same contract as C `reallocarray`.
+#[no_mangle]
+pub unsafe extern "C" fn reallocarray(ptr: *mut c_void, count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size) {
+        Some(size) => realloc(ptr, size),
+        None => {
+            *__errno_location() = ENOMEM;
+            ptr::null_mut()
+        }
+    }
+}
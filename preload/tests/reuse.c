@@ -0,0 +1,39 @@
+/* Frees a block and gets its address back from every allocation function
+ * of the family; each free must be accepted. */
+#include <malloc.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef void *(*alloc_fn)(void);
+
+static void *via_malloc(void) { return malloc(64); }
+static void *via_calloc(void) { return calloc(4, 16); }
+static void *via_aligned_alloc(void) { return aligned_alloc(16, 64); }
+static void *via_memalign(void) { return memalign(16, 64); }
+static void *via_valloc(void) { return valloc(64); }
+static void *via_pvalloc(void) { return pvalloc(64); }
+static void *via_posix_memalign(void) {
+    void *p = NULL;
+    return posix_memalign(&p, 16, 64) == 0 ? p : NULL;
+}
+static void *via_reallocarray(void) { return reallocarray(NULL, 4, 16); }
+
+int main(void) {
+    alloc_fn fns[] = {
+        via_malloc, via_calloc, via_aligned_alloc, via_memalign,
+        via_valloc, via_pvalloc, via_posix_memalign, via_reallocarray,
+    };
+    for (size_t i = 0; i < sizeof fns / sizeof fns[0]; i++) {
+        void *p = malloc(64);
+        free(p);
+        void *q = fns[i]();
+        if (q == NULL) {
+            printf("allocation %zu failed\n", i);
+            return 1;
+        }
+        q = realloc(q, 128);
+        free(q);
+    }
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,39 @@
+//! Runs `reuse.c` under the shim: every allocation function must be
+//! interposed, or a block glibc hands out at a freed address is later
+//! reported as a double free.
+
+#![cfg(all(target_os = "linux", target_env = "gnu"))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Builds the shim, which `cargo test` does not do for a `cdylib`, and
+/// returns its path next to this test's binary.
+fn shim() -> PathBuf {
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo.args(["build", "-q", "-p", "capslock_preload"]);
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    let status = cargo.status().unwrap();
+    assert!(status.success(), "cargo build -p capslock_preload failed");
+    let exe = std::env::current_exe().unwrap();
+    let dir = exe.parent().and_then(Path::parent).unwrap();
+    dir.join("libcapslock_preload.so")
+}
+
+#[test]
+fn freed_addresses_reused_by_every_allocation_function() {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/reuse.c");
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("reuse");
+    let status = Command::new("cc").arg(&source).arg("-o").arg(&program).status().unwrap();
+    assert!(status.success(), "cc failed on {}", source.display());
+
+    let shim = shim();
+    assert!(shim.exists(), "{} not built", shim.display());
+    let output = Command::new(&program).env("LD_PRELOAD", &shim).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{:?}: {}", output.status, stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+    assert!(!stderr.contains("Violation"), "{}", stderr);
+}
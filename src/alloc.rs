@@ -0,0 +1,254 @@
+//! A global allocator that registers every Rust heap allocation with the runtime.
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: CapslockAllocator<System> = CapslockAllocator::new(System);
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::{Cell, UnsafeCell};
+use std::hint;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::runtime::{try_with_scope, Runtime, Scope};
+use crate::violation::Violation;
+
+thread_local! {
+    /// Set while this thread's allocator hook is inside the runtime, so the
+    /// runtime's own allocations are forwarded without being tracked.
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Forwards to `A` and tracks every allocation: `alloc` registers it
+/// (`handle_alloc`), `dealloc` frees it (`handle_free`), and `realloc`
+/// resizes or moves it (`handle_realloc`). Allocations made by the runtime
+/// itself are not tracked.
+///
+/// It always uses the process-wide runtime (`GLOBAL_RT`), so a block may be
+/// freed by another thread than the one that allocated it. Select
+/// `Scope::Process` to check its allocations with the rest of the API.
+///
+/// Use `owner_tag` to get the Tag of an allocation it registered, and do
+/// not also `track_alloc` or `track_free` those allocations by hand.
+pub struct CapslockAllocator<A> {
+    inner: A,
+}
+
+impl<A: GlobalAlloc> CapslockAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+/// Number of untracked blocks `Reused` records before it gives up on them.
+const REUSED_CAPACITY: usize = 64;
+
+/// Blocks `A` handed out while the runtime was busy, mostly to the runtime
+/// itself. Such a block may reuse the address of a freed allocation, whose
+/// region is kept to catch double frees; the region is evicted on the next
+/// hook, before anyone can free the block.
+///
+/// Fixed-size and behind a spin lock, since it is filled from inside the
+/// allocator. On overflow every freed region is evicted instead.
+struct Reused {
+    lock: AtomicBool,
+    blocks: UnsafeCell<ReusedBlocks>,
+}
+
+#[derive(Clone, Copy)]
+struct ReusedBlocks {
+    blocks: [(usize, usize); REUSED_CAPACITY],
+    len: usize,
+    overflowed: bool,
+}
+
+// The blocks are only accessed with `lock` held.
+unsafe impl Sync for Reused {}
+
+static REUSED: Reused = Reused {
+    lock: AtomicBool::new(false),
+    blocks: UnsafeCell::new(ReusedBlocks { blocks: [(0, 0); REUSED_CAPACITY], len: 0, overflowed: false }),
+};
+
+impl Reused {
+    fn with<R>(&self, f: impl FnOnce(&mut ReusedBlocks) -> R) -> R {
+        while self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            hint::spin_loop();
+        }
+        // SAFETY: the lock is held, and `f` cannot reach `REUSED` again.
+        let result = f(unsafe { &mut *self.blocks.get() });
+        self.lock.store(false, Ordering::Release);
+        result
+    }
+
+    fn push(&self, ptr: *mut u8, size: usize) {
+        self.with(|reused| match reused.blocks.get_mut(reused.len) {
+            Some(block) => {
+                *block = (ptr as usize, size);
+                reused.len += 1;
+            }
+            None => reused.overflowed = true,
+        });
+    }
+
+    /// Evicts the freed regions the recorded blocks overlap.
+    fn apply(&self, rt: &mut Runtime) {
+        let reused = self.with(|reused| {
+            let taken = *reused;
+            reused.len = 0;
+            reused.overflowed = false;
+            taken
+        });
+        if reused.overflowed {
+            rt.handle_reuse_all();
+            return;
+        }
+        for &(addr, size) in &reused.blocks[..reused.len] {
+            rt.handle_reuse(addr, size);
+        }
+    }
+}
+
+/// Runs `f` on the process-wide runtime, unless this thread is already
+/// inside the hook or the runtime.
+/// Returns None if `f` did not run, and Some(false) if it reported a
+/// violation that a non-fatal Policy let through.
+///
+/// Unwinding out of an allocator is undefined behaviour, so a violation
+/// under the panic Policy, or a panic inside the runtime, aborts the
+/// process instead.
+fn hook(f: impl FnOnce(&mut Runtime) -> Result<(), Violation>) -> Option<bool> {
+    if IN_HOOK.try_with(|flag| flag.replace(true)) != Ok(false) {
+        return None;
+    }
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        try_with_scope(Scope::Process, |rt| {
+            REUSED.apply(rt);
+            f(rt).map_err(|v| rt.report(v))
+        })
+    }));
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        // The panic hook has already printed the message.
+        Err(_) => std::process::abort(),
+    };
+    if let Some(Err(Some(violation))) = outcome {
+        eprintln!("{}", violation);
+        std::process::abort();
+    }
+    let _ = IN_HOOK.try_with(|flag| flag.set(false));
+    outcome.map(|outcome| outcome.is_ok())
+}
+
+/// Registers a block `A` just returned.
+fn track(ptr: *mut u8, size: usize) {
+    let tracked = hook(|rt| {
+        rt.handle_alloc(ptr as usize, size);
+        Ok(())
+    });
+    if tracked.is_none() {
+        REUSED.push(ptr, size);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CapslockAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            track(ptr, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            track(ptr, layout.size());
+        }
+        ptr
+    }
+
+    /// A rejected free (e.g. a double free) is not forwarded: leaking the
+    /// block is safer than handing it to `A` again.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if hook(|rt| rt.handle_free(ptr as usize)) != Some(false) {
+            self.inner.dealloc(ptr, layout);
+        }
+    }
+
+    /// `A` is called with the runtime held: once it releases the old
+    /// block, another thread may be handed that address, and must not
+    /// register it before the old block is retired.
+    ///
+    /// A rejected realloc (of a freed block, or from inside one) is not
+    /// forwarded, as in `dealloc`, and fails with null.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let mut moved = None;
+        let tracked = hook(|rt| {
+            rt.check_realloc(ptr as usize)?;
+            let new_ptr = *moved.insert(self.inner.realloc(ptr, layout, new_size));
+            if new_ptr.is_null() {
+                return Ok(());
+            }
+            rt.handle_realloc(ptr as usize, new_ptr as usize, new_size).map(drop)
+        });
+        if let Some(new_ptr) = moved {
+            return new_ptr;
+        }
+        if tracked.is_some() {
+            return std::ptr::null_mut();
+        }
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            REUSED.push(new_ptr, new_size);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::System;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::runtime::set_policy;
+    use crate::violation::Policy;
+
+    /// Counts the reallocs that reach it. Never frees, so no address is
+    /// handed out twice.
+    #[derive(Default)]
+    struct Counting {
+        reallocs: AtomicUsize,
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+
+        unsafe fn realloc(&self, _ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            self.reallocs.fetch_add(1, Ordering::Relaxed);
+            System.alloc(Layout::from_size_align_unchecked(new_size, layout.align()))
+        }
+    }
+
+    #[test]
+    fn rejected_realloc_never_reaches_the_inner_allocator() {
+        set_policy(Policy::Count);
+        let alloc = CapslockAllocator::new(Counting::default());
+        let layout = Layout::from_size_align(64, 16).unwrap();
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            assert!(alloc.realloc(ptr.add(16), layout, 128).is_null());
+            let moved = alloc.realloc(ptr, layout, 128);
+            assert!(!moved.is_null());
+            assert!(alloc.realloc(ptr, layout, 256).is_null());
+            alloc.dealloc(moved, Layout::from_size_align(128, 16).unwrap());
+        }
+        assert_eq!(alloc.inner.reallocs.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,9 +1,10 @@
 #include <stdio.h>
+#include <stdlib.h>
 #include <stdint.h>
 
-// Declare the external Rust function we need to call.
-// This notifies the runtime that we are touching the memory.
-extern void capslock_revoke(uintptr_t base);
+// Runtime entry points (capslock_revoke notifies the runtime that we are
+// touching the memory).
+#include "capslock.h"
 
 // A C function that receives a raw pointer from Rust.
 // It modifies the data and triggers a revocation.
@@ -15,6 +16,25 @@ void c_write_access(int* ptr) {
     
     // 2. Trigger Revocation
     // In a full system, this would be injected via compiler instrumentation 
-    // or an LD_PRELOAD allocator shim. For this demo, we call it explicit.
+    // or the LD_PRELOAD allocator shim (preload/). For this demo, we call it explicit.
     capslock_revoke((uintptr_t)ptr);
-}
\ No newline at end of file
+}
+
+// A C library instrumenting its own heap buffer through the C API.
+// Returns the status of a read performed after the buffer was released.
+capslock_status c_use_after_free(void) {
+    int* buf = malloc(4 * sizeof(int));
+    uintptr_t addr = (uintptr_t)buf;
+    capslock_tag owner;
+    capslock_alloc(addr, 4 * sizeof(int), &owner);
+
+    // Interior pointers resolve to the same allocation.
+    buf[3] = 1;
+    capslock_check_write((uintptr_t)&buf[3], sizeof(int), owner);
+
+    capslock_free(addr);
+    free(buf);
+
+    // Stale read: the runtime must reject it.
+    return capslock_check_read(addr + 2 * sizeof(int), sizeof(int), owner);
+}
@@ -0,0 +1,30 @@
+//! Settings selected by `CAPSLOCK_*` environment variables.
+
+/// A setting with a fixed set of named values, read from `ENV_VAR`.
+/// Values are matched case-insensitively, ignoring surrounding whitespace.
+pub trait EnvSetting: Copy + 'static {
+    /// The environment variable selecting the setting.
+    const ENV_VAR: &'static str;
+    /// The setting used when `ENV_VAR` is missing or unknown.
+    const DEFAULT: Self;
+    /// Every accepted value, lowercase, and the setting it selects.
+    const VALUES: &'static [(&'static str, Self)];
+
+    /// Reads `ENV_VAR`, falling back to `DEFAULT`.
+    fn from_env() -> Self {
+        std::env::var(Self::ENV_VAR)
+            .ok()
+            .and_then(|value| parse(&value).ok())
+            .unwrap_or(Self::DEFAULT)
+    }
+}
+
+/// Parses one of `T::VALUES`; the shared `FromStr` implementation.
+pub fn parse<T: EnvSetting>(value: &str) -> Result<T, String> {
+    let value = value.trim().to_ascii_lowercase();
+    T::VALUES
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|&(_, setting)| setting)
+        .ok_or_else(|| format!("unknown {} value: {:?}", T::ENV_VAR, value))
+}
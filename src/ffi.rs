@@ -0,0 +1,221 @@
+//! C ABI for the runtime monitor.
+//! Every entry point mirrors a declaration in `include/capslock.h` and reports
+//! failures through a `Status` code. Nothing in here may unwind into C.
+
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::runtime::{set_policy, set_scope, try_with_runtime, Perm, Runtime, Scope, Tag};
+use crate::violation::{Policy, Violation};
+
+/// Result of a C API call (`capslock_status` in `capslock.h`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// The event was recorded and no violation was detected.
+    Ok = 0,
+    /// The runtime detected an aliasing or provenance violation.
+    Violation = -1,
+    /// The runtime is unavailable (re-entrant call or thread teardown).
+    Busy = -2,
+    /// An argument was out of range (e.g. an unknown permission).
+    InvalidArgument = -3,
+}
+
+impl Perm {
+    /// Decodes a `capslock_perm` value received from C.
+    fn from_raw(raw: c_int) -> Option<Self> {
+        match raw {
+            0 => Some(Perm::Shared),
+            1 => Some(Perm::Mutable),
+            2 => Some(Perm::SharedReadWrite),
+            _ => None,
+        }
+    }
+}
+
+impl Policy {
+    /// Decodes a `capslock_policy` value received from C.
+    fn from_raw(raw: c_int) -> Option<Self> {
+        match raw {
+            0 => Some(Policy::Panic),
+            1 => Some(Policy::Abort),
+            2 => Some(Policy::Log),
+            3 => Some(Policy::Count),
+            _ => None,
+        }
+    }
+}
+
+/// Runs `f` against the selected runtime, converting violations and an
+/// unavailable runtime into status codes. Panics are caught as a last resort.
+/// Violations go through the runtime's Policy, except that `Panic` never
+/// unwinds into C: the caller only receives `Status::Violation`.
+fn call<R>(f: impl FnOnce(&mut Runtime) -> Result<R, Violation>) -> Result<R, Status> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        try_with_runtime(|rt| {
+            f(rt).map_err(|v| {
+                rt.report(v);
+                Status::Violation
+            })
+        })
+    }));
+
+    match result {
+        Ok(Some(outcome)) => outcome,
+        Ok(None) => Err(Status::Busy),
+        Err(_) => Err(Status::Violation),
+    }
+}
+
+/// Writes the Tag produced by `f` to `out`.
+///
+/// # Safety
+/// `out` must be null or valid for writes.
+unsafe fn call_with_tag(out: *mut Tag, f: impl FnOnce(&mut Runtime) -> Result<Tag, Violation>) -> Status {
+    if out.is_null() {
+        return Status::InvalidArgument;
+    }
+    match call(f) {
+        Ok(tag) => {
+            *out = tag;
+            Status::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Collapses a Tag-less call into its status code.
+fn status(result: Result<(), Status>) -> Status {
+    result.err().unwrap_or(Status::Ok)
+}
+
+/// Registers a new allocation of `size` bytes starting at `base` and stores
+/// the owner's Tag in `out_tag`.
+///
+/// # Safety
+/// `out_tag` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn capslock_alloc(base: usize, size: usize, out_tag: *mut Tag) -> Status {
+    call_with_tag(out_tag, |rt| Ok(rt.handle_alloc(base, size)))
+}
+
+/// Registers a reborrow of (`parent`, `parent_tag`) with permission `perm`
+/// and stores the derived pointer's Tag in `out_tag`.
+///
+/// # Safety
+/// `out_tag` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn capslock_borrow(
+    parent: usize,
+    parent_tag: Tag,
+    perm: c_int,
+    out_tag: *mut Tag,
+) -> Status {
+    let perm = match Perm::from_raw(perm) {
+        Some(perm) => perm,
+        None => return Status::InvalidArgument,
+    };
+    call_with_tag(out_tag, |rt| rt.handle_reborrow(parent, parent_tag, perm))
+}
+
+/// Validates a read of `len` bytes through (`addr`, `tag`) with reader semantics.
+#[no_mangle]
+pub extern "C" fn capslock_check_read(addr: usize, len: usize, tag: Tag) -> Status {
+    status(call(|rt| rt.handle_read(addr, len, tag)))
+}
+
+/// Validates a write of `len` bytes through (`addr`, `tag`) with writer semantics.
+#[no_mangle]
+pub extern "C" fn capslock_check_write(addr: usize, len: usize, tag: Tag) -> Status {
+    status(call(|rt| rt.handle_write(addr, len, tag)))
+}
+
+/// Ends the borrow (`addr`, `tag`) and every pointer derived from it.
+#[no_mangle]
+pub extern "C" fn capslock_release(addr: usize, tag: Tag) -> Status {
+    status(call(|rt| rt.handle_release(addr, tag)))
+}
+
+/// Protects (`addr`, `tag`) until `capslock_unprotect(tag)`: any access that
+/// would invalidate it in the meantime is a violation. Protectors nest.
+#[no_mangle]
+pub extern "C" fn capslock_protect(addr: usize, tag: Tag) -> Status {
+    status(call(|rt| rt.handle_protect(addr, tag)))
+}
+
+/// Releases one protector added by `capslock_protect`.
+#[no_mangle]
+pub extern "C" fn capslock_unprotect(tag: Tag) -> Status {
+    status(call(|rt| {
+        rt.handle_unprotect(tag);
+        Ok(())
+    }))
+}
+
+/// Marks `size` bytes at `addr` as interior-mutable: writes there through
+/// shared pointers are allowed and leave sibling readers valid.
+#[no_mangle]
+pub extern "C" fn capslock_mark_interior_mut(addr: usize, size: usize) -> Status {
+    status(call(|rt| {
+        rt.handle_interior_mut(addr, size);
+        Ok(())
+    }))
+}
+
+/// Tracks a `realloc` of the allocation starting at `old` to `new_size` bytes
+/// at `new` and stores the owner's Tag of the result in `out_tag`.
+/// In place, the allocation keeps its Tags; moved, the old ones are revoked.
+///
+/// # Safety
+/// `out_tag` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn capslock_realloc(old: usize, new: usize, new_size: usize, out_tag: *mut Tag) -> Status {
+    call_with_tag(out_tag, |rt| rt.handle_realloc(old, new, new_size))
+}
+
+/// Releases the allocation starting at `base`, revoking every derived pointer.
+/// A second release, or one of an address inside the allocation, returns
+/// `Status::Violation` (double or invalid free).
+#[no_mangle]
+pub extern "C" fn capslock_free(base: usize) -> Status {
+    status(call(|rt| rt.handle_free(base)))
+}
+
+/// Revocation hook for foreign (C) code.
+/// Deep-revokes the allocation containing `base`. The signature has no way
+/// to report failures, so a protected pointer in it only reaches the
+/// Policy, and the allocation is left untouched.
+#[no_mangle]
+pub extern "C" fn capslock_revoke(base: usize) {
+    let _ = call(|rt| rt.handle_revoke(base));
+}
+
+/// Selects what happens on a violation (`capslock_policy` in `capslock.h`),
+/// for every thread.
+#[no_mangle]
+pub extern "C" fn capslock_set_policy(policy: c_int) -> Status {
+    match Policy::from_raw(policy) {
+        Some(policy) => set_policy(policy),
+        None => return Status::InvalidArgument,
+    }
+    Status::Ok
+}
+
+/// Number of violations reported to the selected runtime so far.
+#[no_mangle]
+pub extern "C" fn capslock_violation_count() -> usize {
+    call(|rt| Ok(rt.violation_count())).unwrap_or(0)
+}
+
+/// Selects the thread-local (`0`) or process-wide (`1`) runtime
+/// (`capslock_scope` in `capslock.h`).
+#[no_mangle]
+pub extern "C" fn capslock_set_scope(scope: c_int) -> Status {
+    match scope {
+        0 => set_scope(Scope::Thread),
+        1 => set_scope(Scope::Process),
+        _ => return Status::InvalidArgument,
+    }
+    Status::Ok
+}
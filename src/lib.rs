@@ -0,0 +1,9 @@
+//! CapsLock-lite: a runtime monitor for pointer provenance across the Rust/C boundary.
+
+pub mod alloc;
+pub mod env;
+pub mod ffi;
+pub mod model;
+pub mod runtime;
+pub mod shadow;
+pub mod violation;
@@ -1,44 +1,349 @@
-mod runtime; 
-use runtime::{track_alloc, track_borrow, check_access, Perm};
+use capslock_lite::env::EnvSetting;
+use capslock_lite::ffi::Status;
+use capslock_lite::runtime::{
+    live_borrows, protect, set_policy, set_scope, track_alloc, track_alloc_bytes, track_borrow,
+    track_borrow_scoped, track_free, track_interior_mut, track_realloc, check_read, check_write, try_check_read,
+    try_check_write, try_track_borrow, try_track_free, violation_count, Perm, Scope,
+};
+use capslock_lite::model::Model;
+use capslock_lite::runtime::Runtime;
+use capslock_lite::violation::{Policy, Violation};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+extern "C" {
+    // Defined in src/bad_actor.c
+    fn c_write_access(ptr: *mut i32);
+    fn c_use_after_free() -> Status;
+}
+
+/// Number of steps that printed FAILURE; the demo exits non-zero if any did.
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Prints a step's FAILURE line and counts it.
+macro_rules! failure {
+    ($($arg:tt)*) => {{
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+        println!("FAILURE: {}", format_args!($($arg)*));
+    }};
+}
+
+/// A small instrumented workload of valid Rust idioms.
+/// Returns how many of its operations the runtime's model rejected.
+fn idiom_workload(rt: &mut Runtime) -> usize {
+    let base = 0x1000;
+    let owner = rt.handle_alloc(base, 16);
+    let mut rejected = 0;
+
+    // Owner reads while a & is lent out, then the & reads.
+    if let Ok(shared) = rt.handle_reborrow(base, owner, Perm::Shared) {
+        rejected += rt.handle_read(base, 8, owner).is_err() as usize;
+        rejected += rt.handle_read(base, 8, shared).is_err() as usize;
+    }
+
+    // Two-phase borrow: `v.push(v.len())`.
+    if let Ok(two_phase) = rt.handle_reborrow(base, owner, Perm::Mutable) {
+        rejected += rt.handle_read(base, 8, owner).is_err() as usize;
+        rejected += rt.handle_write(base, 8, two_phase).is_err() as usize;
+    }
+    rejected
+}
 
 fn main() {
     println!(":: CapsLock-lite: Final Verified Test (Strict Logic) ::\n");
 
+    // The steps below expect violations to panic, whatever CAPSLOCK_MODE says.
+    // They pass under either CAPSLOCK_MODEL; only step 15 expects a different outcome.
+    set_policy(Policy::Panic);
+    let model = Model::from_env();
+
     let mut data = 100;
     let root_ptr = &mut data as *mut i32;
     
     // 1. Allocation
-    track_alloc(root_ptr);
+    let root = track_alloc(root_ptr);
     println!("[1] Allocated Root Owner.");
 
-    // 2. Shared Borrow A (Reader)
-    let ref_a = unsafe { root_ptr.add(1) }; 
-    track_borrow(root_ptr, ref_a, Perm::Shared);
-    println!("[2] Created ref_a (Shared/Reader).");
+    // 2. Mutable Borrow C (Writer) - Reserved until its first write
+    // Both borrows point at the same address; only their Tags tell them apart.
+    let mut_c = root_ptr;
+    let tag_c = track_borrow(root_ptr, root, Perm::Mutable);
+    println!("[2] Created mut_c (Mutable/Writer).");
 
-    // 3. Mutable Borrow C (Writer) - Dormant
-    let mut_c = unsafe { root_ptr.add(2) }; 
-    track_borrow(root_ptr, mut_c, Perm::Mutable);
-    println!("[3] Created mut_c (Mutable/Writer).");
+    // 3. Access mut_c (Writer) - the write activates it
+    check_write(mut_c, tag_c);
+    println!("[3] mut_c wrote (Reserved -> Active).");
 
-    // 4. Access ref_a (Reader)
-    // RULE: Reading a Shared ptr MUST kill any dormant Mutable siblings.
-    print!("[4] Accessing ref_a... "); 
-    check_access(ref_a); 
-    println!("Success. (Logic Check: This Read should have killed the Writer 'mut_c').");
+    // 4. Shared Borrow A (Reader), then read through it
+    // RULE: A foreign read MUST freeze any Active Mutable sibling.
+    let ref_a = root_ptr as *const i32;
+    let tag_a = track_borrow(root_ptr, root, Perm::Shared);
+    print!("[4] Created ref_a (Shared/Reader) and accessed it... ");
+    check_read(ref_a, tag_a);
+    println!("Success. (Logic Check: This Read should have frozen the Writer 'mut_c').");
 
     // 5. Access mut_c (Writer)
     // EXPECTATION: This MUST fail. If it succeeds, our security is broken.
     println!("[5] Accessing mut_c... Expecting Panic (Correct Behavior).");
     
     let result = std::panic::catch_unwind(|| {
-        check_access(mut_c);
+        check_write(mut_c, tag_c);
     });
 
     match result {
-        Ok(_) => println!("FAILURE: mut_c is still alive! Security hole detected."),
-        Err(_) => println!("SUCCESS: Violation caught. The Reader correctly revoked the Writer."),
+        Ok(_) => failure!("mut_c is still writable! Security hole detected."),
+        Err(_) => println!("SUCCESS: Violation caught. The Reader correctly froze the Writer."),
+    }
+
+    // 6. Foreign Write (C revokes the allocation)
+    let boxed = Box::into_raw(Box::new(7));
+    let owner = track_alloc(boxed);
+    println!("\n[6] Allocated Box and handed it to C.");
+    unsafe { c_write_access(boxed) };
+
+    // 7. Access after the foreign revocation
+    // EXPECTATION: The owner itself was revoked by C, so this MUST fail.
+    print!("[7] Accessing Box after C revoked it... ");
+
+    match try_check_read(boxed, owner) {
+        Ok(_) => failure!("Box is still alive! Foreign revocation was ignored."),
+        Err(v @ Violation::UseAfterRevoke { .. }) => println!("SUCCESS: {}", v),
+        Err(v) => failure!("Unexpected violation: {}", v),
+    }
+    drop(unsafe { Box::from_raw(boxed) });
+
+    // 8. C instruments its own allocation through the C API
+    // EXPECTATION: The read after capslock_free is reported as a status code.
+    print!("\n[8] C reads its own buffer after freeing it... ");
+    match unsafe { c_use_after_free() } {
+        Status::Violation => println!("SUCCESS: C received CAPSLOCK_VIOLATION."),
+        status => failure!("C received {:?}.", status),
+    }
+
+    // 9. Owner reads while a Shared borrow is outstanding
+    // RULE: A read is not a write, so the reader must survive it.
+    let mut value = 5;
+    let owner_ptr = &mut value as *mut i32;
+    let owner = track_alloc(owner_ptr);
+    let reader = track_borrow(owner_ptr, owner, Perm::Shared);
+    print!("\n[9] Owner reads, then ref_r reads... ");
+    check_read(owner_ptr, owner);
+    let result = std::panic::catch_unwind(|| {
+        check_read(owner_ptr, reader);
+    });
+
+    match result {
+        Ok(_) => println!("SUCCESS: ref_r survived the owner's read."),
+        Err(_) => failure!("The owner's read was treated as a write."),
+    }
+
+    // 10. Reporting-only mode (what staging runs with CAPSLOCK_MODE=count)
+    // EXPECTATION: The stale write is counted instead of panicking.
+    set_policy(Policy::Count);
+    let before = violation_count();
+    print!("\n[10] Writing through ref_a under Policy::Count... ");
+    check_write(ref_a, tag_a);
+
+    match violation_count() - before {
+        1 => println!("SUCCESS: Violation counted, execution continued."),
+        n => failure!("{} violations counted.", n),
+    }
+    set_policy(Policy::Panic);
+
+    // 11. Cross-thread hand-off (process-wide runtime)
+    // EXPECTATION: A worker's write through its own borrow revokes the
+    // main thread's sibling borrow, even though they ran on different threads.
+    // The worker's borrow is the newer one, so it stays valid under both models.
+    set_scope(Scope::Process);
+    let shared = Box::into_raw(Box::new(0u64));
+    let owner = track_alloc(shared);
+    let main_tag = track_borrow(shared, owner, Perm::Mutable);
+    let worker_tag = track_borrow(shared, owner, Perm::Mutable);
+    let addr = shared as usize;
+    std::thread::spawn(move || check_write(addr as *const u64, worker_tag))
+        .join()
+        .unwrap();
+
+    print!("\n[11] Writing through main thread's borrow after the worker wrote... ");
+    match try_check_write(shared, main_tag) {
+        Ok(_) => failure!("The worker's write was invisible to this thread."),
+        Err(v) => println!("SUCCESS: {}", v),
+    }
+    drop(unsafe { Box::from_raw(shared) });
+
+    // 12. Use-After-Free, even when the allocator hands the address out again
+    let first = Box::into_raw(Box::new(1u32));
+    let stale = track_alloc(first);
+    track_free(first);
+    drop(unsafe { Box::from_raw(first) });
+    let second = Box::into_raw(Box::new(2u32));
+    track_alloc(second);
+
+    print!("\n[12] Reading through the freed Box (address reused: {})... ", first == second);
+    match try_check_read(first, stale) {
+        Err(v @ Violation::UseAfterFree { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+
+    // 13. Double Free
+    track_free(second);
+    print!("[13] Freeing the second Box twice... ");
+    match try_track_free(second) {
+        Err(v @ Violation::DoubleFree { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+    drop(unsafe { Box::from_raw(second) });
+
+    // 14. Address reused without a tracked free (ABA)
+    // The second registration replaces the first; the old Tag is a previous generation.
+    let mut slot = 0u8;
+    let slot_ptr = &mut slot as *mut u8;
+    let old_gen = track_alloc(slot_ptr);
+    let new_gen = track_alloc(slot_ptr);
+    check_write(slot_ptr, new_gen);
+
+    print!("\n[14] Writing through the previous generation's Tag... ");
+    match try_check_write(slot_ptr, old_gen) {
+        Err(v @ Violation::StaleAllocation { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+
+    // 15. Two-phase borrow (`v.push(v.len())`)
+    // RULE: A Reserved &mut tolerates the owner's read until it writes.
+    // Stacked Borrows has no Reserved state, so there the read revokes it.
+    let mut len = 0usize;
+    let vec_ptr = &mut len as *mut usize;
+    let owner = track_alloc(vec_ptr);
+    let two_phase = track_borrow(vec_ptr, owner, Perm::Mutable);
+    check_read(vec_ptr, owner);
+
+    print!("\n[15] Writing through a two-phase &mut after the owner read... ");
+    match (model, try_check_write(vec_ptr, two_phase)) {
+        (Model::TreeBorrows, Ok(_)) => println!("SUCCESS: The Reserved borrow survived the read."),
+        (Model::StackedBorrows, Err(v)) => println!("SUCCESS: Stacked Borrows rejects it: {}", v),
+        (_, Ok(_)) => failure!("The two-phase borrow survived the read."),
+        (_, Err(v)) => failure!("{}", v),
+    }
+
+    // 16. Same workload, different aliasing models
+    println!("\n[16] Comparing aliasing models on the same workload:");
+    for model in [Model::TreeBorrows, Model::StackedBorrows] {
+        let mut rt = Runtime::with_model(model);
+        let rejected = idiom_workload(&mut rt);
+        println!("     {:<16} rejected {} valid operation(s).", rt.model_name(), rejected);
+    }
+
+    // 17. Interior mutability (`&Cell<T>`)
+    // RULE: Inside an UnsafeCell, a write through one & leaves the other & valid.
+    let cell = std::cell::Cell::new(0u32);
+    let cell_ptr = cell.as_ptr() as *const u32;
+    let owner = track_alloc(cell_ptr);
+    track_interior_mut(cell_ptr);
+    let writer = track_borrow(cell_ptr, owner, Perm::Shared);
+    let reader = track_borrow(cell_ptr, owner, Perm::Shared);
+    check_write(cell_ptr, writer);
+
+    print!("\n[17] Reading through a sibling & after a Cell::set through another &... ");
+    match try_check_read(cell_ptr, reader) {
+        Ok(_) => println!("SUCCESS: The sibling reader survived the interior write."),
+        Err(v) => failure!("{}", v),
     }
 
+    // 18. Protector: a &mut passed into a call stays valid for the whole call
+    // EXPECTATION: A callback writing through the caller's owner during the
+    // call would revoke the argument, so it is rejected on the spot.
+    let mut buf = [0u8; 4];
+    let buf_ptr = buf.as_mut_ptr();
+    let owner = track_alloc_bytes(buf_ptr, buf.len());
+    let arg = track_borrow(buf_ptr, owner, Perm::Mutable);
+    let guard = protect(buf_ptr, arg);
+
+    print!("\n[18] Writing through the owner while the argument is protected... ");
+    match try_check_write(buf_ptr, owner) {
+        Err(v @ Violation::ProtectedRevoke { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+    drop(guard);
+
+    // 19. Borrows end when they go out of scope
+    // EXPECTATION: The scoped &mut is retired on drop; its Tag is dead afterwards.
+    let mut counter = 0u32;
+    let counter_ptr = &mut counter as *mut u32;
+    let owner = track_alloc(counter_ptr);
+    let ended = {
+        let scoped = track_borrow_scoped(counter_ptr, owner, Perm::Mutable);
+        check_write(counter_ptr, scoped.tag());
+        scoped.tag()
+    };
+    check_write(counter_ptr, owner);
+
+    print!("\n[19] Writing through a &mut after its scope ended... ");
+    match try_check_write(counter_ptr, ended) {
+        Err(v @ Violation::UseAfterRevoke { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+
+    // 20. Shared-to-mut cast (`&T as *const T as *mut T`)
+    // RULE: A derivation may only narrow the parent's permission.
+    let mut flag = false;
+    let flag_ptr = &mut flag as *mut bool;
+    let owner = track_alloc(flag_ptr);
+    let shared = track_borrow(flag_ptr, owner, Perm::Shared);
+
+    print!("\n[20] Deriving a Mutable pointer from a Shared one... ");
+    match try_track_borrow(flag_ptr, shared, Perm::Mutable) {
+        Err(v @ Violation::PermissionEscalation { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+
+    // 21. Per-allocation inspection
+    // EXPECTATION: Only the owner and the borrow still in scope are listed.
+    let mut pair = (0u16, 0u16);
+    let pair_ptr = &mut pair as *mut (u16, u16);
+    let owner = track_alloc(pair_ptr);
+    let kept = track_borrow(pair_ptr, owner, Perm::Shared);
+    drop(track_borrow_scoped(pair_ptr, owner, Perm::Shared));
+
+    print!("\n[21] Listing the live borrows of an allocation... ");
+    match live_borrows(pair_ptr).as_slice() {
+        [first, second] if *first == owner && *second == kept => println!("SUCCESS: {:?}", [first, second]),
+        other => failure!("{:?}", other),
+    }
+
+    // 22. realloc that moves the block
+    // EXPECTATION: Growing in place keeps the Tag; moving revokes it.
+    let small = Box::into_raw(Box::new([0u8; 4]));
+    let owner = track_alloc(small);
+    let kept = track_realloc(small, small, 4);
+    let large = Box::into_raw(Box::new([0u8; 8]));
+    let moved = track_realloc(small, large, 8);
+    check_write(large, moved);
+
+    print!("\n[22] Writing through the Tag of the block realloc moved away from... ");
+    match (kept == owner, try_check_write(small, owner)) {
+        (true, Err(v @ Violation::UseAfterFree { .. })) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+    track_free(large);
+    drop(unsafe { Box::from_raw(small) });
+    drop(unsafe { Box::from_raw(large) });
+
+    // 23. Spatial bounds
+    // EXPECTATION: One past the end of an i32 is outside its allocation.
+    let mut num = 0i32;
+    let num_ptr = &mut num as *mut i32;
+    let owner = track_alloc(num_ptr);
+    check_write(num_ptr, owner);
+
+    print!("\n[23] Reading the i32 after num_ptr... ");
+    match try_check_read(num_ptr.wrapping_add(1), owner) {
+        Err(v @ Violation::OutOfBounds { .. }) => println!("SUCCESS: {}", v),
+        other => failure!("{:?}", other),
+    }
+
+    let failures = FAILURES.load(Ordering::Relaxed);
+    if failures > 0 {
+        println!("\n:: Test Complete. {} step(s) FAILED. ::", failures);
+        std::process::exit(1);
+    }
     println!("\n:: Test Complete. System is Secure. ::");
-}
\ No newline at end of file
+}
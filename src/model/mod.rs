@@ -0,0 +1,140 @@
+//! Aliasing models: the rules deciding which accesses and reborrows are allowed.
+//! The Runtime handles addresses, Tags and reporting, and delegates every
+//! event to an `AliasingModel`, so different models can be compared on the
+//! same instrumented workload. Every tracked allocation owns its own model
+//! instance, dropped when the allocation is freed.
+
+mod stacked;
+mod tree;
+
+pub use stacked::StackedBorrows;
+pub use tree::{BorrowTree, State};
+
+use crate::env::{self, EnvSetting};
+use crate::runtime::Perm;
+
+/// Why a model rejected an access or a reborrow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// The pointer, or one it was derived from, has been invalidated.
+    Revoked,
+    /// The pointer only grants read access.
+    ReadOnly,
+    /// The access would invalidate or freeze this node, which is protected.
+    Protected(usize),
+}
+
+/// Node ID of the allocation's owner in every model instance.
+pub const ROOT: usize = 0;
+
+/// Low bits of a node ID: the node's slot in the model's storage. The high
+/// bits are the slot's generation, bumped whenever the slot is reused, so
+/// the IDs of a reclaimed node never match the node that replaced it.
+const SLOT_BITS: u32 = 24;
+const SLOT_MASK: usize = (1 << SLOT_BITS) - 1;
+/// A slot at this generation is not reused again, so IDs never wrap around.
+/// On 64-bit targets that takes 2^40 reuses of one slot, so in practice no
+/// slot is ever retired.
+const MAX_GENERATION: usize = usize::MAX >> SLOT_BITS;
+
+/// The event interface every aliasing model implements, for the borrows of
+/// a single allocation. Nodes are identified by the `usize` IDs the model
+/// hands out, starting with the owner at `ROOT`; the Runtime packs them into
+/// Tags together with the allocation ID.
+pub trait AliasingModel: Send {
+    /// Human-readable name, for reports and comparisons.
+    fn name(&self) -> &'static str;
+
+    /// Derives a new pointer with permission `perm` from `parent`.
+    fn on_retag(&mut self, parent: usize, perm: Perm) -> Result<usize, Fault>;
+
+    /// Validates and applies a read through `id`.
+    fn on_read(&mut self, id: usize) -> Result<(), Fault>;
+
+    /// Validates and applies a write through `id`.
+    /// `interior` is set when the written location was marked interior-mutable
+    /// (`UnsafeCell`): shared borrows may write there without invalidating
+    /// each other.
+    fn on_write(&mut self, id: usize, interior: bool) -> Result<(), Fault>;
+
+    /// Invalidates every pointer of the allocation, root included.
+    fn on_revoke(&mut self);
+
+    /// Ends the borrow `id` (it went out of scope). It and everything derived
+    /// from it become invalid without affecting, or being affected by, later
+    /// accesses through other pointers. Fails with `Fault::Protected` if one
+    /// of them is protected.
+    fn on_release(&mut self, id: usize) -> Result<(), Fault>;
+
+    /// Adds a protector to `id`: until the matching `unprotect`, any access
+    /// that would invalidate it, or freeze it once it has written, fails
+    /// with `Fault::Protected`.
+    /// Protectors nest. Fails if `id` is already invalid.
+    fn protect(&mut self, id: usize) -> Result<(), Fault>;
+
+    /// Removes one protector from `id`.
+    fn unprotect(&mut self, id: usize);
+
+    /// A protected node of the allocation, if any: ending the whole
+    /// allocation (a free, or `on_revoke`) would invalidate it.
+    fn protected(&self) -> Option<usize>;
+
+    /// The permission `id` was created with, if the model still knows it.
+    fn perm(&self, id: usize) -> Option<Perm>;
+
+    /// Whether `id` was handed out by this instance, even if it has been
+    /// invalidated, or forgotten, since.
+    fn derived(&self, id: usize) -> bool;
+
+    /// The node and the nodes it was derived from, innermost first.
+    fn lineage(&self, id: usize) -> Vec<usize>;
+
+    /// The nodes that can still be used, root first.
+    fn live_nodes(&self) -> Vec<usize>;
+}
+
+/// Selects the aliasing model of a Runtime.
+/// Read from the `CAPSLOCK_MODEL` environment variable (`tree` or `stacked`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    /// `BorrowTree` (default).
+    TreeBorrows,
+    /// `StackedBorrows`.
+    StackedBorrows,
+}
+
+impl EnvSetting for Model {
+    const ENV_VAR: &'static str = "CAPSLOCK_MODEL";
+    const DEFAULT: Self = Model::TreeBorrows;
+    const VALUES: &'static [(&'static str, Self)] = &[("tree", Model::TreeBorrows), ("stacked", Model::StackedBorrows)];
+}
+
+impl Model {
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::TreeBorrows => "Tree Borrows",
+            Model::StackedBorrows => "Stacked Borrows",
+        }
+    }
+
+    /// Creates the model instance of a fresh allocation, holding only its owner.
+    pub fn build(self) -> Box<dyn AliasingModel> {
+        self.constructor()()
+    }
+
+    /// `build` for this model, as a plain function.
+    pub(crate) fn constructor(self) -> fn() -> Box<dyn AliasingModel> {
+        match self {
+            Model::TreeBorrows => || Box::new(BorrowTree::new()),
+            Model::StackedBorrows => || Box::new(StackedBorrows::new()),
+        }
+    }
+}
+
+impl std::str::FromStr for Model {
+    type Err = String;
+
+    fn from_str(model: &str) -> Result<Self, Self::Err> {
+        env::parse(model)
+    }
+}
@@ -0,0 +1,427 @@
+//! Stacked Borrows: a stack of granted permissions per location.
+//! A location is a whole allocation: every allocation owns exactly one
+//! borrow stack, whichever bytes of it an access covers.
+
+use super::{AliasingModel, Fault, MAX_GENERATION, ROOT, SLOT_BITS, SLOT_MASK};
+use crate::runtime::Perm;
+
+/// An entry of a borrow stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    /// Grants reads and writes (`&mut T` and owners).
+    Unique(usize),
+    /// Grants reads only (`&T`), and writes inside interior-mutable locations.
+    SharedReadOnly(usize),
+    /// Grants reads and writes, shared with its neighbours (`&Cell<T>`).
+    SharedReadWrite(usize),
+}
+
+impl Item {
+    fn id(self) -> usize {
+        match self {
+            Item::Unique(id) | Item::SharedReadOnly(id) | Item::SharedReadWrite(id) => id,
+        }
+    }
+}
+
+/// Bookkeeping for one derived pointer.
+/// The derivation links are only used for provenance checks and reports;
+/// validity is decided by the stack alone.
+#[derive(Debug, Clone)]
+struct Derivation {
+    id: usize,
+    /// The parent's ID rather than its slot, so the lineage of a reclaimed
+    /// derivation stops where a slot was reused.
+    parent: Option<usize>,
+    permission: Perm,
+    /// Number of active protectors (calls the pointer was passed into).
+    protectors: u32,
+    /// Whether its item is still on the stack.
+    on_stack: bool,
+    /// Number of derivations with this one as parent, not yet reclaimable.
+    children: u32,
+    /// The next slot on the reclaimable list.
+    next_reclaimable: Option<usize>,
+    /// The last release pass that decided whether it derives from the
+    /// released pointer, and the answer, as `pass << 1 | derived`.
+    mark: u64,
+}
+
+/// Stacked Borrows for one allocation; the owner is `ROOT`.
+///
+/// - A read through a Tag pops every Unique item above its topmost item.
+/// - A write through a Tag pops every item above its topmost item, which
+///   must be Unique. Writes through SharedReadWrite items (and through
+///   SharedReadOnly items into interior-mutable locations) only pop the
+///   Unique items above, so sibling readers survive.
+/// - A reborrow performs a read (Shared, SharedReadWrite) or write (Mutable)
+///   through the parent, then pushes the new item on top.
+/// - Popping the item of a protected pointer is a violation.
+/// - Releasing a pointer removes its items and those of pointers derived
+///   from it, wherever they are in the stack.
+///
+/// Stricter than Tree Borrows: there is no Reserved state, so a `&mut`
+/// does not survive a read through its parent (two-phase borrows fail).
+///
+/// A derivation whose item was popped and which has no derivations left
+/// below it is reclaimed when a new pointer is derived, under the next
+/// generation of its ID (as in `BorrowTree`), so the Tags of the old
+/// pointer keep failing and a churning allocation stays as large as its
+/// peak number of live borrows.
+pub struct StackedBorrows {
+    derivations: Vec<Derivation>,
+    stack: Vec<Item>,
+    /// Slots of reclaimable derivations, chained through `next_reclaimable`.
+    reclaimable: Option<usize>,
+    /// Bumped by every release, invalidating every `mark`.
+    pass: u64,
+}
+
+impl Default for StackedBorrows {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StackedBorrows {
+    /// A stack holding only the allocation's owner.
+    pub fn new() -> Self {
+        let root = Derivation {
+            id: ROOT,
+            parent: None,
+            permission: Perm::Mutable,
+            protectors: 0,
+            on_stack: true,
+            children: 0,
+            next_reclaimable: None,
+            mark: 0,
+        };
+        Self { derivations: vec![root], stack: vec![Item::Unique(ROOT)], reclaimable: None, pass: 0 }
+    }
+
+    /// The slot of derivation `id`, unless it never existed or its slot was reused.
+    fn slot(&self, id: usize) -> Option<usize> {
+        let slot = id & SLOT_MASK;
+        self.derivations.get(slot).filter(|d| d.id == id).map(|_| slot)
+    }
+
+    /// Position of the topmost item carrying `id` (its granting item).
+    fn granting(&self, id: usize) -> Result<usize, Fault> {
+        self.stack.iter().rposition(|item| item.id() == id).ok_or(Fault::Revoked)
+    }
+
+    /// Removes the items above the granting item of `id` that `keep` rejects.
+    /// Fails without modifying the stack if one of them is protected.
+    fn pop_above(&mut self, id: usize, keep: impl Fn(Item) -> bool) -> Result<(), Fault> {
+        let pos = self.granting(id)?;
+        let mut popped = self.stack[pos + 1..].iter().filter(|&&item| !keep(item));
+        if let Some(item) = popped.find(|item| self.derivations[item.id() & SLOT_MASK].protectors > 0) {
+            return Err(Fault::Protected(item.id()));
+        }
+        self.retain_items(pos + 1, |_, item| keep(item));
+        Ok(())
+    }
+
+    /// Keeps the items from position `from` up that `keep` accepts, given
+    /// the derivations, in order. Only the items above `from` are visited.
+    fn retain_items(&mut self, from: usize, keep: impl Fn(&[Derivation], Item) -> bool) {
+        let mut kept = from;
+        for idx in from..self.stack.len() {
+            let item = self.stack[idx];
+            if keep(&self.derivations, item) {
+                self.stack[kept] = item;
+                kept += 1;
+            } else {
+                self.pop_item(item.id() & SLOT_MASK);
+            }
+        }
+        self.stack.truncate(kept);
+    }
+
+    /// Records that the item of `slot` left the stack, and makes it, and
+    /// the ancestors it was the last one to keep, reclaimable.
+    fn pop_item(&mut self, mut slot: usize) {
+        self.derivations[slot].on_stack = false;
+        while slot != ROOT && !self.derivations[slot].on_stack && self.derivations[slot].children == 0 {
+            self.derivations[slot].next_reclaimable = self.reclaimable;
+            self.reclaimable = Some(slot);
+            let Some(parent) = self.derivations[slot].parent else { break };
+            slot = parent & SLOT_MASK;
+            self.derivations[slot].children -= 1;
+        }
+    }
+
+    /// Takes a slot off the reclaimable list. Slots at `MAX_GENERATION` are
+    /// dropped from the list instead.
+    fn reclaim(&mut self) -> Option<usize> {
+        while let Some(slot) = self.reclaimable {
+            self.reclaimable = self.derivations[slot].next_reclaimable.take();
+            if self.derivations[slot].id >> SLOT_BITS < MAX_GENERATION {
+                return Some(slot);
+            }
+        }
+        None
+    }
+}
+
+impl AliasingModel for StackedBorrows {
+    fn name(&self) -> &'static str {
+        "Stacked Borrows"
+    }
+
+    fn on_retag(&mut self, parent: usize, perm: Perm) -> Result<usize, Fault> {
+        match perm {
+            Perm::Shared | Perm::SharedReadWrite => self.on_read(parent)?,
+            Perm::Mutable => self.on_write(parent, false)?,
+        }
+
+        let mut derivation = Derivation {
+            id: 0,
+            parent: Some(parent),
+            permission: perm,
+            protectors: 0,
+            on_stack: true,
+            children: 0,
+            next_reclaimable: None,
+            mark: 0,
+        };
+        let id = match self.reclaim() {
+            Some(slot) => {
+                derivation.id = (((self.derivations[slot].id >> SLOT_BITS) + 1) << SLOT_BITS) | slot;
+                self.derivations[slot] = derivation;
+                self.derivations[slot].id
+            }
+            None => {
+                let slot = self.derivations.len();
+                assert!(slot <= SLOT_MASK, "[CapsLock] Borrow stack exceeded 2^24 derivations");
+                derivation.id = slot;
+                self.derivations.push(derivation);
+                slot
+            }
+        };
+        // The parent's item granted the access above, so it is still live.
+        self.derivations[parent & SLOT_MASK].children += 1;
+        self.stack.push(match perm {
+            Perm::Shared => Item::SharedReadOnly(id),
+            Perm::SharedReadWrite => Item::SharedReadWrite(id),
+            Perm::Mutable => Item::Unique(id),
+        });
+        Ok(id)
+    }
+
+    fn on_read(&mut self, id: usize) -> Result<(), Fault> {
+        // Readers above the granting item survive; writers do not.
+        self.pop_above(id, |item| !matches!(item, Item::Unique(_)))
+    }
+
+    fn on_write(&mut self, id: usize, interior: bool) -> Result<(), Fault> {
+        match self.stack[self.granting(id)?] {
+            Item::Unique(_) => self.pop_above(id, |_| false),
+            Item::SharedReadOnly(_) if !interior => Err(Fault::ReadOnly),
+            // A shared write: other shared writers above survive, and so
+            // do readers of an interior-mutable location.
+            _ => self.pop_above(id, |item| match item {
+                Item::Unique(_) => false,
+                Item::SharedReadOnly(_) => interior,
+                Item::SharedReadWrite(_) => true,
+            }),
+        }
+    }
+
+    fn on_revoke(&mut self) {
+        self.retain_items(0, |_, _| false);
+    }
+
+    fn on_release(&mut self, id: usize) -> Result<(), Fault> {
+        if self.slot(id).is_none() {
+            return Ok(());
+        }
+        self.pass += 1;
+        let mut protected = None;
+        for item in &self.stack {
+            let idx = item.id();
+            if derived_from(&mut self.derivations, self.pass, idx, id) && self.derivations[idx & SLOT_MASK].protectors > 0 {
+                protected.get_or_insert(idx);
+            }
+        }
+        if let Some(idx) = protected {
+            return Err(Fault::Protected(idx));
+        }
+        let released = self.pass << 1 | 1;
+        self.retain_items(0, |derivations, item| derivations[item.id() & SLOT_MASK].mark != released);
+        Ok(())
+    }
+
+    fn protect(&mut self, id: usize) -> Result<(), Fault> {
+        self.granting(id)?;
+        self.derivations[id & SLOT_MASK].protectors += 1;
+        Ok(())
+    }
+
+    fn unprotect(&mut self, id: usize) {
+        if let Some(slot) = self.slot(id) {
+            let d = &mut self.derivations[slot];
+            d.protectors = d.protectors.saturating_sub(1);
+        }
+    }
+
+    fn protected(&self) -> Option<usize> {
+        self.stack.iter().map(|item| item.id()).find(|&id| self.derivations[id & SLOT_MASK].protectors > 0)
+    }
+
+    fn perm(&self, id: usize) -> Option<Perm> {
+        self.slot(id).map(|slot| self.derivations[slot].permission)
+    }
+
+    /// IDs of a generation up to the slot's current one.
+    fn derived(&self, id: usize) -> bool {
+        self.derivations.get(id & SLOT_MASK).is_some_and(|d| id >> SLOT_BITS <= d.id >> SLOT_BITS)
+    }
+
+    /// Up to the first derivation whose slot was reused.
+    fn lineage(&self, id: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut curr = self.slot(id);
+        while let Some(slot) = curr {
+            let d = &self.derivations[slot];
+            path.push(d.id);
+            curr = d.parent.and_then(|parent| self.slot(parent));
+        }
+        path
+    }
+
+    /// Pointers with an item on the stack, root first.
+    fn live_nodes(&self) -> Vec<usize> {
+        let mut live: Vec<usize> = self.stack.iter().map(|item| item.id()).collect();
+        live.sort_unstable();
+        live.dedup();
+        live
+    }
+}
+
+/// Whether `idx` is `id` or was derived from it, recorded in the `mark` of
+/// `idx` and the derivations between them for release `pass`, so that a
+/// release walks each derivation once. Takes the derivations alone so it
+/// can run while the stack is borrowed. `idx` has an item on the stack, so
+/// none of its ancestors has been reused.
+fn derived_from(derivations: &mut [Derivation], pass: u64, idx: usize, id: usize) -> bool {
+    let mut curr = Some(idx);
+    let derived = loop {
+        match curr {
+            Some(curr) if curr == id => break true,
+            Some(slot) if derivations[slot & SLOT_MASK].mark >> 1 == pass => {
+                break derivations[slot & SLOT_MASK].mark & 1 == 1;
+            }
+            Some(slot) => curr = derivations[slot & SLOT_MASK].parent,
+            None => break false,
+        }
+    };
+    let mut curr = Some(idx);
+    while let Some(slot) = curr {
+        let d = &mut derivations[slot & SLOT_MASK];
+        if d.mark >> 1 == pass {
+            break;
+        }
+        d.mark = pass << 1 | derived as u64;
+        curr = if slot == id { None } else { d.parent };
+    }
+    derived
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_item_blocks_popping_access_without_modifying_stack() {
+        let mut stack = StackedBorrows::new();
+        let a = stack.on_retag(ROOT, Perm::Mutable).unwrap();
+        let b = stack.on_retag(a, Perm::Mutable).unwrap();
+        stack.protect(b).unwrap();
+        assert_eq!(stack.protected(), Some(b));
+
+        assert_eq!(stack.on_write(ROOT, false), Err(Fault::Protected(b)));
+        assert_eq!(stack.on_read(a), Err(Fault::Protected(b)));
+        assert_eq!(stack.live_nodes(), vec![ROOT, a, b]);
+        stack.on_write(b, false).unwrap();
+
+        stack.unprotect(b);
+        assert_eq!(stack.protected(), None);
+        stack.on_read(a).unwrap();
+        assert_eq!(stack.on_read(b), Err(Fault::Revoked));
+        assert_eq!(stack.protect(b), Err(Fault::Revoked));
+    }
+
+    #[test]
+    fn release_removes_derived_items_and_stale_ids_keep_failing() {
+        let mut stack = StackedBorrows::new();
+        let a = stack.on_retag(ROOT, Perm::SharedReadWrite).unwrap();
+        let b = stack.on_retag(a, Perm::Shared).unwrap();
+        let c = stack.on_retag(ROOT, Perm::Shared).unwrap();
+
+        stack.protect(b).unwrap();
+        assert_eq!(stack.on_release(a), Err(Fault::Protected(b)));
+        assert_eq!(stack.live_nodes(), vec![ROOT, a, b, c]);
+        stack.unprotect(b);
+
+        // Items derived from `a` go, wherever they are; `c` stays.
+        stack.on_release(a).unwrap();
+        assert_eq!(stack.live_nodes(), vec![ROOT, c]);
+        assert_eq!(stack.on_read(b), Err(Fault::Revoked));
+        stack.on_read(c).unwrap();
+
+        // The next pointer reuses the released slot under a new generation.
+        let d = stack.on_retag(ROOT, Perm::Shared).unwrap();
+        assert_eq!(d & SLOT_MASK, a & SLOT_MASK);
+        assert_ne!(d, a);
+        assert!(stack.derived(a));
+        assert_eq!(stack.perm(a), None);
+        assert!(stack.lineage(a).is_empty());
+        assert_eq!(stack.on_release(a), Ok(()));
+        assert_eq!(stack.on_read(a), Err(Fault::Revoked));
+        assert_eq!(stack.lineage(d), vec![d, ROOT]);
+    }
+
+    #[test]
+    fn shared_read_only_writes_only_inside_interior_and_keep_readers() {
+        let mut stack = StackedBorrows::new();
+        let writer = stack.on_retag(ROOT, Perm::Shared).unwrap();
+        let reader = stack.on_retag(ROOT, Perm::Shared).unwrap();
+
+        assert_eq!(stack.on_write(writer, false), Err(Fault::ReadOnly));
+        stack.on_write(writer, true).unwrap();
+        stack.on_read(reader).unwrap();
+        assert_eq!(stack.live_nodes(), vec![ROOT, writer, reader]);
+    }
+
+    #[test]
+    fn shared_read_write_siblings_survive_each_others_writes() {
+        let mut stack = StackedBorrows::new();
+        let x = stack.on_retag(ROOT, Perm::SharedReadWrite).unwrap();
+        let y = stack.on_retag(ROOT, Perm::SharedReadWrite).unwrap();
+        stack.on_write(x, false).unwrap();
+        stack.on_write(y, false).unwrap();
+        stack.on_write(x, false).unwrap();
+        assert_eq!(stack.live_nodes(), vec![ROOT, x, y]);
+
+        // A Unique item above them is popped, though.
+        let unique = stack.on_retag(y, Perm::Mutable).unwrap();
+        stack.on_write(x, false).unwrap();
+        assert_eq!(stack.on_read(unique), Err(Fault::Revoked));
+        stack.on_read(y).unwrap();
+    }
+
+    #[test]
+    fn churning_borrows_reuse_one_slot() {
+        let mut stack = StackedBorrows::new();
+        let first = stack.on_retag(ROOT, Perm::Mutable).unwrap();
+        stack.on_release(first).unwrap();
+        for _ in 0..1000 {
+            let id = stack.on_retag(ROOT, Perm::Mutable).unwrap();
+            stack.on_write(id, false).unwrap();
+            stack.on_release(id).unwrap();
+        }
+        assert_eq!(stack.derivations.len(), 2);
+        assert_eq!(stack.on_read(first), Err(Fault::Revoked));
+    }
+}
@@ -0,0 +1,790 @@
+//! Tree Borrows: a tree of derived pointers with a per-node permission state.
+
+use super::{AliasingModel, Fault, MAX_GENERATION, ROOT, SLOT_BITS, SLOT_MASK};
+use crate::runtime::{Access, Perm};
+
+/// Per-node permission state, modelled on Tree Borrows.
+/// Every access updates every node of the allocation's tree: nodes on the path
+/// from the accessed node to the root see a *local* access, all others a
+/// *foreign* one.
+///
+/// | State    | local read | local write | foreign read | foreign write |
+/// |----------|------------|-------------|--------------|---------------|
+/// | Reserved | Reserved   | Active      | Reserved     | Disabled      |
+/// | Active   | Active     | Active      | Frozen       | Disabled      |
+/// | Frozen   | Frozen     | violation   | Frozen       | Disabled      |
+/// | Cell     | Cell       | Cell        | Cell         | Cell          |
+/// | Disabled | violation  | violation   | Disabled     | Disabled      |
+///
+/// Inside a location marked interior-mutable, Frozen behaves like Cell: shared
+/// borrows may write there and survive each other's writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    /// A fresh `&mut` that has not written yet (two-phase borrow).
+    /// Tolerates foreign reads, so the owner may still read through it.
+    Reserved,
+    /// A `&mut` (or owner) that has written. A foreign read freezes it.
+    Active,
+    /// Read-only: a `&T`, or a `&mut T` that saw a foreign read.
+    Frozen,
+    /// Shared and writable (`Perm::SharedReadWrite`, e.g. `&Cell<T>`).
+    /// Unaffected by foreign accesses.
+    Cell,
+    /// Revoked; any further local access is a violation.
+    Disabled,
+}
+
+impl State {
+    /// The state a freshly derived pointer starts in.
+    fn initial(perm: Perm) -> Self {
+        match perm {
+            Perm::Shared => State::Frozen,
+            Perm::SharedReadWrite => State::Cell,
+            Perm::Mutable => State::Reserved,
+        }
+    }
+
+    /// Applies an access to this state. Returns None if the access is a violation.
+    /// `interior` is set when the accessed location is interior-mutable.
+    fn transition(self, access: Access, local: bool, interior: bool) -> Option<Self> {
+        match (self, access, local) {
+            (State::Cell, _, _) => Some(State::Cell),
+            (State::Frozen, _, _) if interior => Some(State::Frozen),
+            (State::Disabled, _, true) => None,
+            (State::Frozen, Access::Write, true) => None,
+            (State::Reserved, Access::Write, true) => Some(State::Active),
+            (state, Access::Read, true) | (state, Access::Write, true) => Some(state),
+            (_, Access::Write, false) => Some(State::Disabled),
+            (State::Active, Access::Read, false) => Some(State::Frozen),
+            (state, Access::Read, false) => Some(state),
+        }
+    }
+}
+
+/// A node in the Borrow Tree representing a specific pointer derivation.
+/// Tracks lineage and current permission state. Children form an intrusive
+/// list (first/last child, prev/next sibling), so unlinking a node and
+/// walking a subtree never allocate.
+#[derive(Debug, Clone)]
+struct Node {
+    id: usize,
+    /// The parent's ID rather than its slot, so the lineage of a Disabled
+    /// node stops where a slot was reused.
+    parent: Option<usize>,
+    first_child: Option<usize>,
+    last_child: Option<usize>,
+    prev_sibling: Option<usize>,
+    next_sibling: Option<usize>,
+    permission: Perm, 
+    state: State,
+    /// Number of active protectors (calls the pointer was passed into).
+    protectors: u32,
+    /// Protectors of this node and its descendants, so disabling the subtree
+    /// knows whether it may without visiting it.
+    protected_below: u32,
+    /// Distance from the root.
+    depth: u32,
+    /// Last `epoch` at which no node on the path to the root was Disabled.
+    valid_at: u64,
+    /// Last `chain_version` at which every Active node was on the path to the root.
+    chain_at: u64,
+}
+
+impl Node {
+    fn new(id: usize, parent: Option<usize>, permission: Perm, state: State) -> Self {
+        Self {
+            id,
+            parent,
+            first_child: None,
+            last_child: None,
+            prev_sibling: None,
+            next_sibling: None,
+            permission,
+            state,
+            protectors: 0,
+            protected_below: 0,
+            depth: 0,
+            valid_at: 0,
+            chain_at: 0,
+        }
+    }
+}
+
+/// The node of the last write that left nothing else to invalidate: every
+/// node off its path is Disabled or a Cell, every node on it Active or a Cell.
+/// Writes through it, or through the one node derived from it since, skip
+/// the tree walk.
+#[derive(Debug, Clone, Copy)]
+struct Tip {
+    node: usize,
+    child: Option<usize>,
+}
+
+/// The core data structure enforcing the Aliasing Model.
+/// It maintains the hierarchy of borrows of one allocation to track pointer
+/// provenance; node IDs are local to the allocation, the owner being `ROOT`.
+///
+/// Revocation is lazy: disabling a node marks only that node, and its
+/// descendants are invalid because their path to the root crosses it. It is
+/// unlinked from its parent's children, so accesses stop visiting it.
+/// Every disable bumps `epoch`; a node's path is only walked again once the
+/// epoch moved past the last walk. The whole tree is dropped with the allocation.
+///
+/// Unlinked nodes are reclaimed when new ones are derived: the slot is
+/// reused under the next generation of its ID, so the old node's Tags keep
+/// failing, and its children are Disabled and reclaimed in turn. Under
+/// steady churn the tree therefore stays as large as its peak number of
+/// live borrows. The methods below work on slots; only the `AliasingModel`
+/// methods take and return IDs.
+///
+/// Reads and writes never allocate; only deriving a node may grow `nodes`.
+pub struct BorrowTree {
+    nodes: Vec<Node>,
+    /// Bumped whenever a node is Disabled, invalidating every `valid_at`.
+    epoch: u64,
+    /// The deepest Active node. Every Active node lies on its path: only
+    /// local writes activate nodes, and a write disables every Active node
+    /// off its path. It may have lost its state since.
+    active: Option<usize>,
+    /// Bumped whenever a node becomes Active, invalidating every `chain_at`.
+    chain_version: u64,
+    tip: Option<Tip>,
+    /// Unlinked Disabled nodes whose slots can be reused, chained through
+    /// `next_sibling`.
+    reclaimable: Option<usize>,
+}
+
+impl Default for BorrowTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BorrowTree {
+    /// A tree holding only the allocation's owner.
+    /// The root is implicitly Mutable and starts Active.
+    pub fn new() -> Self {
+        let mut root = Node::new(ROOT, None, Perm::Mutable, State::Active);
+        root.valid_at = 1;
+        root.chain_at = 1;
+        Self {
+            nodes: vec![root],
+            epoch: 1,
+            active: Some(ROOT),
+            chain_version: 1,
+            tip: Some(Tip { node: ROOT, child: None }),
+            reclaimable: None,
+        }
+    }
+
+    /// The slot of node `id`, unless it never existed or its slot was reused.
+    fn slot(&self, id: usize) -> Option<usize> {
+        let slot = id & SLOT_MASK;
+        self.nodes.get(slot).filter(|node| node.id == id).map(|_| slot)
+    }
+
+    /// The parent's slot. Only for nodes whose parent still holds it: those
+    /// not yet Disabled, and those with protectors.
+    fn parent(&self, id: usize) -> Option<usize> {
+        self.nodes[id].parent.map(|parent| parent & SLOT_MASK)
+    }
+
+    /// Derives a child pointer from a valid parent and returns its ID.
+    /// Returns None if the parent is already invalid.
+    fn spawn_child(&mut self, parent_id: usize, perm: Perm) -> Option<usize> {
+        if self.nodes[parent_id].state == State::Disabled {
+            return None;
+        }
+        let parent = &self.nodes[parent_id];
+        let mut node = Node::new(0, Some(parent.id), perm, State::initial(perm));
+        node.depth = parent.depth + 1;
+        node.valid_at = parent.valid_at;
+        node.chain_at = parent.chain_at;
+        node.prev_sibling = parent.last_child;
+        let id = match self.reclaim() {
+            Some(slot) => {
+                node.id = (((self.nodes[slot].id >> SLOT_BITS) + 1) << SLOT_BITS) | slot;
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                let slot = self.nodes.len();
+                assert!(slot <= SLOT_MASK, "[CapsLock] Borrow tree exceeded 2^24 nodes");
+                node.id = slot;
+                self.nodes.push(node);
+                slot
+            }
+        };
+
+        match self.nodes[parent_id].last_child {
+            Some(last) => self.nodes[last].next_sibling = Some(id),
+            None => self.nodes[parent_id].first_child = Some(id),
+        }
+        self.nodes[parent_id].last_child = Some(id);
+        self.tip = match self.tip {
+            Some(Tip { node, child: None }) if node == parent_id => Some(Tip { node, child: Some(id) }),
+            _ => None,
+        };
+        Some(self.nodes[id].id)
+    }
+
+    /// Takes a slot off the reclaimable list. The children of its node are
+    /// Disabled and become reclaimable in turn, so none of them is left
+    /// with a parent slot that was reused. Slots at `MAX_GENERATION` are
+    /// dropped from the list instead.
+    fn reclaim(&mut self) -> Option<usize> {
+        while let Some(slot) = self.reclaimable {
+            self.reclaimable = self.nodes[slot].next_sibling.take();
+            self.nodes[slot].last_child = None;
+            let mut child = self.nodes[slot].first_child.take();
+            while let Some(idx) = child {
+                child = self.nodes[idx].next_sibling;
+                let node = &mut self.nodes[idx];
+                node.state = State::Disabled;
+                node.prev_sibling = None;
+                node.next_sibling = self.reclaimable;
+                self.reclaimable = Some(idx);
+            }
+            if self.nodes[slot].id >> SLOT_BITS < MAX_GENERATION {
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    /// Unlinks a Disabled node from its parent, and makes it reclaimable.
+    fn detach(&mut self, id: usize) {
+        let Some(parent) = self.parent(id) else { return };
+        let (prev, next) = (self.nodes[id].prev_sibling.take(), self.nodes[id].next_sibling.take());
+        match prev {
+            Some(prev) => self.nodes[prev].next_sibling = next,
+            None => self.nodes[parent].first_child = next,
+        }
+        match next {
+            Some(next) => self.nodes[next].prev_sibling = prev,
+            None => self.nodes[parent].last_child = prev,
+        }
+        self.nodes[id].next_sibling = self.reclaimable;
+        self.reclaimable = Some(id);
+    }
+
+    /// Invalidates a node and, through it, its entire subtree.
+    fn disable(&mut self, id: usize) {
+        self.nodes[id].state = State::Disabled;
+        self.detach(id);
+        self.epoch += 1;
+    }
+
+    /// The node after `curr` in a pre-order walk of the subtree of `top`,
+    /// skipping the children of `curr` unless `descend` is set.
+    fn next_in_subtree(&self, curr: usize, top: usize, descend: bool) -> Option<usize> {
+        if descend && self.nodes[curr].first_child.is_some() {
+            return self.nodes[curr].first_child;
+        }
+        let mut curr = curr;
+        while curr != top {
+            let node = &self.nodes[curr];
+            if node.next_sibling.is_some() {
+                return node.next_sibling;
+            }
+            curr = self.parent(curr)?;
+        }
+        None
+    }
+
+    /// Whether `ancestor` is `id` or one of the nodes it was derived from.
+    fn is_ancestor(&self, ancestor: usize, mut id: usize) -> bool {
+        let depth = self.nodes[ancestor].depth;
+        while self.nodes[id].depth > depth {
+            match self.parent(id) {
+                Some(parent) => id = parent,
+                None => break,
+            }
+        }
+        id == ancestor
+    }
+
+    /// Fails if `id` or a node it was derived from is Disabled. Walks up only
+    /// to the first node already checked in the current epoch.
+    fn check_path(&mut self, id: usize) -> Result<(), Fault> {
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            let node = &self.nodes[idx];
+            if node.valid_at == self.epoch {
+                break;
+            }
+            if node.state == State::Disabled {
+                return Err(Fault::Revoked);
+            }
+            curr = self.parent(idx);
+        }
+
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            let node = &mut self.nodes[idx];
+            if node.valid_at == self.epoch {
+                break;
+            }
+            node.valid_at = self.epoch;
+            curr = node.parent.map(|parent| parent & SLOT_MASK);
+        }
+        Ok(())
+    }
+
+    /// The ID of a protected node in the subtree of `id`, for reporting.
+    fn protected_in(&self, id: usize) -> usize {
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            let node = &self.nodes[idx];
+            if node.protectors > 0 {
+                return node.id;
+            }
+            curr = self.next_in_subtree(idx, id, node.protected_below > 0);
+        }
+        self.nodes[id].id
+    }
+
+    /// Ends the borrow `id` and everything derived from it: the subtree is
+    /// Disabled and detached, so later accesses no longer visit it.
+    /// Fails, without modifying anything, on a protected node.
+    fn release(&mut self, id: usize) -> Result<(), Fault> {
+        if self.nodes[id].protected_below > 0 {
+            return Err(Fault::Protected(self.protected_in(id)));
+        }
+        // The subtree may be reclaimed, so `active` must not stay in it.
+        if self.active.is_some_and(|active| self.is_ancestor(id, active)) {
+            self.active = self.parent(id);
+        }
+        if self.nodes[id].state != State::Disabled {
+            self.disable(id);
+        }
+        self.tip = None;
+        Ok(())
+    }
+
+    /// Applies a read through `id`: a foreign read freezes the Active nodes
+    /// off its path, which are the ones below it on the path of `active`.
+    /// Fails, without modifying anything, if one of them is protected.
+    fn read(&mut self, id: usize) -> Result<(), Fault> {
+        self.check_path(id)?;
+        if self.nodes[id].chain_at == self.chain_version {
+            return Ok(());
+        }
+        // Find the first Active ancestor, checked first so a protected
+        // node can still reject the read.
+        let mut stop = self.active;
+        while let Some(top) = stop {
+            let node = &self.nodes[top];
+            if node.state == State::Active {
+                if self.is_ancestor(top, id) {
+                    break;
+                }
+                if node.protectors > 0 {
+                    return Err(Fault::Protected(node.id));
+                }
+            }
+            stop = self.parent(top);
+        }
+        while self.active != stop {
+            let top = self.active.expect("`stop` lies on the path of `active`");
+            if self.nodes[top].state == State::Active {
+                self.nodes[top].state = State::Frozen;
+                self.tip = None;
+            }
+            self.active = self.parent(top);
+        }
+        self.nodes[id].chain_at = self.chain_version;
+        Ok(())
+    }
+
+    /// Applies a write through `id` to the whole tree of its allocation.
+    /// On a violation nothing is modified.
+    fn write(&mut self, id: usize, interior: bool) -> Result<(), Fault> {
+        if let Some(tip) = self.tip {
+            if let Some(result) = self.write_at_tip(tip, id, interior) {
+                return result;
+            }
+        }
+        self.check_path(id)?;
+
+        // 1. Local transitions (the node and its ancestors), none of them
+        // Disabled by now. Validate before mutating.
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            if self.nodes[idx].state.transition(Access::Write, true, interior).is_none() {
+                return Err(self.fault(idx));
+            }
+            curr = self.parent(idx);
+        }
+
+        // 2. Foreign transitions: the subtrees hanging off the path. Checked
+        // first so a protected node can still reject the access.
+        self.write_off_path(id, interior, false)?;
+        self.write_off_path(id, interior, true)?;
+
+        // Every Active node off the path is in a disabled subtree now.
+        self.active = None;
+        let mut activated = false;
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            let node = &mut self.nodes[idx];
+            let next = node.state.transition(Access::Write, true, interior).unwrap_or(State::Disabled);
+            activated |= next == State::Active && node.state != State::Active;
+            node.state = next;
+            if next == State::Active && self.active.is_none() {
+                self.active = Some(idx);
+            }
+            curr = node.parent.map(|parent| parent & SLOT_MASK);
+        }
+        if activated {
+            self.chain_version += 1;
+        }
+        self.nodes[id].chain_at = self.chain_version;
+        // Interior writes leave Frozen nodes alive off the path.
+        self.tip = (!interior).then_some(Tip { node: id, child: None });
+        Ok(())
+    }
+
+    /// Applies the foreign side of a write through `id`, subtree by subtree
+    /// along its path. With `apply` unset, only checks that no protected
+    /// node would be disabled.
+    fn write_off_path(&mut self, id: usize, interior: bool, apply: bool) -> Result<(), Fault> {
+        let mut below = None;
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            let mut child = self.nodes[idx].first_child;
+            while let Some(head) = child {
+                child = self.nodes[head].next_sibling;
+                if Some(head) != below {
+                    self.foreign_write(head, interior, apply)?;
+                }
+            }
+            below = Some(idx);
+            curr = self.parent(idx);
+        }
+        Ok(())
+    }
+
+    /// A foreign write to the subtree of `head`: a node the write disables
+    /// takes its subtree with it, the children of nodes it leaves alone
+    /// (Cells, and Frozen nodes of an interior-mutable location) are visited
+    /// in turn.
+    fn foreign_write(&mut self, head: usize, interior: bool, apply: bool) -> Result<(), Fault> {
+        let mut curr = Some(head);
+        while let Some(idx) = curr {
+            let node = &self.nodes[idx];
+            let disabled = matches!(node.state.transition(Access::Write, false, interior), Some(State::Disabled) | None);
+            if disabled && node.protected_below > 0 {
+                return Err(Fault::Protected(self.protected_in(idx)));
+            }
+            curr = self.next_in_subtree(idx, head, !disabled);
+            if disabled && apply {
+                self.disable(idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a write through the tip, or through the node derived from it
+    /// since, in constant time. Returns None if the tree has to be walked.
+    fn write_at_tip(&mut self, tip: Tip, id: usize, interior: bool) -> Option<Result<(), Fault>> {
+        if id == tip.node {
+            if let Some(child) = tip.child {
+                let node = &self.nodes[child];
+                match node.state.transition(Access::Write, false, interior) {
+                    Some(State::Cell) => {}
+                    Some(State::Disabled) | None => {
+                        if node.protected_below > 0 {
+                            return Some(Err(Fault::Protected(self.protected_in(child))));
+                        }
+                        self.disable(child);
+                    }
+                    Some(_) => return None,
+                }
+            }
+            self.tip = Some(Tip { node: id, child: None });
+            return Some(Ok(()));
+        }
+        if tip.child != Some(id) {
+            return None;
+        }
+
+        let node = &mut self.nodes[id];
+        match node.state.transition(Access::Write, true, interior) {
+            None => return Some(Err(self.fault(id))),
+            Some(State::Active) => {
+                if node.state != State::Active {
+                    node.state = State::Active;
+                    self.active = Some(id);
+                    self.chain_version += 1;
+                }
+                self.nodes[id].chain_at = self.chain_version;
+            }
+            Some(State::Cell) => {}
+            Some(_) => return None,
+        }
+        self.tip = Some(Tip { node: id, child: None });
+        Some(Ok(()))
+    }
+
+    /// Maps the node that rejected an access to the reason.
+    fn fault(&self, id: usize) -> Fault {
+        match self.nodes[id].state {
+            // A Frozen node on the path only rejects writes.
+            State::Frozen => Fault::ReadOnly,
+            _ => Fault::Revoked,
+        }
+    }
+
+    /// Collects the IDs of node `id` and all its ancestors, innermost first,
+    /// up to the first one whose slot was reused.
+    fn lineage(&self, id: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut curr = self.slot(id);
+        while let Some(idx) = curr {
+            let node = &self.nodes[idx];
+            path.push(node.id);
+            curr = node.parent.and_then(|parent| self.slot(parent));
+        }
+        path
+    }
+
+    /// Nodes that can still be used: reachable from the root without
+    /// crossing a Disabled node.
+    fn live_nodes(&self) -> Vec<usize> {
+        let mut live = Vec::new();
+        let mut curr = Some(ROOT);
+        while let Some(idx) = curr {
+            let usable = self.nodes[idx].state != State::Disabled;
+            if usable {
+                live.push(self.nodes[idx].id);
+            }
+            curr = self.next_in_subtree(idx, ROOT, usable);
+        }
+        live
+    }
+}
+
+impl AliasingModel for BorrowTree {
+    fn name(&self) -> &'static str {
+        "Tree Borrows"
+    }
+
+    /// Retagging reads through the parent (Tree Borrows' implicit read on reborrow).
+    fn on_retag(&mut self, parent: usize, perm: Perm) -> Result<usize, Fault> {
+        let parent = self.slot(parent).ok_or(Fault::Revoked)?;
+        self.read(parent)?;
+        self.spawn_child(parent, perm).ok_or(Fault::Revoked)
+    }
+
+    fn on_read(&mut self, id: usize) -> Result<(), Fault> {
+        self.read(self.slot(id).ok_or(Fault::Revoked)?)
+    }
+
+    fn on_write(&mut self, id: usize, interior: bool) -> Result<(), Fault> {
+        self.write(self.slot(id).ok_or(Fault::Revoked)?, interior)
+    }
+
+    fn on_revoke(&mut self) {
+        self.nodes[ROOT].state = State::Disabled;
+        self.epoch += 1;
+        self.tip = None;
+    }
+
+    fn on_release(&mut self, id: usize) -> Result<(), Fault> {
+        match self.slot(id) {
+            Some(id) => self.release(id),
+            None => Ok(()),
+        }
+    }
+
+    fn protect(&mut self, id: usize) -> Result<(), Fault> {
+        let id = self.slot(id).ok_or(Fault::Revoked)?;
+        self.check_path(id)?;
+        self.nodes[id].protectors += 1;
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            self.nodes[idx].protected_below += 1;
+            curr = self.parent(idx);
+        }
+        Ok(())
+    }
+
+    fn unprotect(&mut self, id: usize) {
+        let Some(id) = self.slot(id).filter(|&slot| self.nodes[slot].protectors > 0) else { return };
+        self.nodes[id].protectors -= 1;
+        let mut curr = Some(id);
+        while let Some(idx) = curr {
+            self.nodes[idx].protected_below -= 1;
+            curr = self.parent(idx);
+        }
+    }
+
+    fn protected(&self) -> Option<usize> {
+        (self.nodes[ROOT].protected_below > 0).then(|| self.protected_in(ROOT))
+    }
+
+    fn perm(&self, id: usize) -> Option<Perm> {
+        self.slot(id).map(|slot| self.nodes[slot].permission)
+    }
+
+    /// IDs of a generation up to the slot's current one.
+    fn derived(&self, id: usize) -> bool {
+        self.nodes.get(id & SLOT_MASK).is_some_and(|node| id >> SLOT_BITS <= node.id >> SLOT_BITS)
+    }
+
+    fn lineage(&self, id: usize) -> Vec<usize> {
+        BorrowTree::lineage(self, id)
+    }
+
+    fn live_nodes(&self) -> Vec<usize> {
+        BorrowTree::live_nodes(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The state of node `id`, which must still hold its slot.
+    fn state(tree: &BorrowTree, id: usize) -> State {
+        tree.nodes[tree.slot(id).expect("node was reclaimed")].state
+    }
+
+    #[test]
+    fn tip_write_through_child_activates_it() {
+        let mut tree = BorrowTree::new();
+        let a = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        assert_eq!(state(&tree, a), State::Reserved);
+
+        // The child derived since the last write is written through the tip.
+        assert!(matches!(tree.tip, Some(Tip { node: ROOT, child: Some(child) }) if child == a));
+        tree.on_write(a, false).unwrap();
+        assert_eq!(state(&tree, a), State::Active);
+        assert_eq!(tree.active, Some(a));
+
+        // A write through the parent is foreign to the child.
+        tree.on_write(ROOT, false).unwrap();
+        assert_eq!(tree.on_write(a, false), Err(Fault::Revoked));
+        assert_eq!(tree.live_nodes(), vec![ROOT]);
+    }
+
+    #[test]
+    fn tip_write_through_parent_keeps_cell_child() {
+        let mut tree = BorrowTree::new();
+        let cell = tree.on_retag(ROOT, Perm::SharedReadWrite).unwrap();
+        tree.on_write(ROOT, false).unwrap();
+        tree.on_write(cell, false).unwrap();
+        assert_eq!(state(&tree, cell), State::Cell);
+        assert_eq!(tree.live_nodes(), vec![ROOT, cell]);
+    }
+
+    #[test]
+    fn read_after_tip_write_freezes_writer() {
+        let mut tree = BorrowTree::new();
+        let a = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        tree.on_write(a, false).unwrap();
+
+        tree.on_read(ROOT).unwrap();
+        assert_eq!(state(&tree, a), State::Frozen);
+        assert_eq!(tree.active, Some(ROOT));
+        assert_eq!(tree.on_write(a, false), Err(Fault::ReadOnly));
+        tree.on_read(a).unwrap();
+
+        // The tip was dropped, so a write through the owner walks the tree.
+        tree.on_write(ROOT, false).unwrap();
+        assert_eq!(tree.on_read(a), Err(Fault::Revoked));
+    }
+
+    #[test]
+    fn release_ends_subtree_and_stale_ids_keep_failing() {
+        let mut tree = BorrowTree::new();
+        let a = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        let b = tree.on_retag(a, Perm::Mutable).unwrap();
+        tree.on_write(b, false).unwrap();
+        assert_eq!(tree.active, Some(b));
+
+        tree.on_release(a).unwrap();
+        assert_eq!(tree.active, Some(ROOT));
+        assert_eq!(tree.on_read(a), Err(Fault::Revoked));
+        assert_eq!(tree.on_read(b), Err(Fault::Revoked));
+        assert_eq!(tree.live_nodes(), vec![ROOT]);
+
+        // The next borrow reuses the released slot under a new generation.
+        let c = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        assert_eq!(c & SLOT_MASK, a & SLOT_MASK);
+        assert_ne!(c, a);
+        assert!(tree.derived(a));
+        assert_eq!(tree.perm(a), None);
+        assert!(tree.lineage(a).is_empty());
+        assert_eq!(tree.on_write(a, false), Err(Fault::Revoked));
+        assert_eq!(tree.on_retag(a, Perm::Shared), Err(Fault::Revoked));
+        assert_eq!(tree.on_release(a), Ok(()));
+        tree.on_write(c, false).unwrap();
+        assert_eq!(tree.lineage(c), vec![c, ROOT]);
+    }
+
+    #[test]
+    fn churning_borrows_reuse_one_slot() {
+        let mut tree = BorrowTree::new();
+        let first = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        tree.on_release(first).unwrap();
+        for _ in 0..1000 {
+            let id = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+            tree.on_write(id, false).unwrap();
+            tree.on_release(id).unwrap();
+        }
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.on_read(first), Err(Fault::Revoked));
+    }
+
+    #[test]
+    fn protected_node_rejects_release_and_foreign_write() {
+        let mut tree = BorrowTree::new();
+        let a = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        let b = tree.on_retag(a, Perm::Mutable).unwrap();
+        tree.protect(b).unwrap();
+
+        assert_eq!(tree.on_release(a), Err(Fault::Protected(b)));
+        assert_eq!(tree.on_write(ROOT, false), Err(Fault::Protected(b)));
+        // Nothing was modified.
+        tree.on_write(b, false).unwrap();
+
+        tree.unprotect(b);
+        tree.on_write(ROOT, false).unwrap();
+        assert_eq!(tree.on_read(b), Err(Fault::Revoked));
+        assert_eq!(tree.protect(b), Err(Fault::Revoked));
+    }
+
+    #[test]
+    fn protected_reports_a_protected_descendant_until_unprotected() {
+        let mut tree = BorrowTree::new();
+        let a = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        let b = tree.on_retag(a, Perm::Shared).unwrap();
+        assert_eq!(tree.protected(), None);
+
+        tree.protect(b).unwrap();
+        assert_eq!(tree.protected(), Some(b));
+        tree.unprotect(b);
+        assert_eq!(tree.protected(), None);
+        tree.on_revoke();
+        assert_eq!(tree.on_read(b), Err(Fault::Revoked));
+    }
+
+    #[test]
+    fn protected_active_node_rejects_freezing_read() {
+        let mut tree = BorrowTree::new();
+        let a = tree.on_retag(ROOT, Perm::Mutable).unwrap();
+        tree.protect(a).unwrap();
+        tree.on_write(a, false).unwrap();
+
+        assert_eq!(tree.on_read(ROOT), Err(Fault::Protected(a)));
+        assert_eq!(state(&tree, a), State::Active);
+        // A read through the protected node itself is local.
+        tree.on_read(a).unwrap();
+
+        tree.unprotect(a);
+        tree.on_read(ROOT).unwrap();
+        assert_eq!(state(&tree, a), State::Frozen);
+    }
+}
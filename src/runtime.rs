@@ -1,242 +1,1112 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use lazy_static::lazy_static;
+
+use crate::env::EnvSetting;
+use crate::model::{AliasingModel, Fault, Model, ROOT};
+use crate::shadow::{Shadow, ShadowIndex};
+use crate::violation::{Policy, Violation};
 
 /// Represents the permission level of a pointer, derived from Rust's ownership model.
+/// The discriminants are part of the C ABI (`capslock_perm` in `capslock.h`).
+#[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Perm {
     /// Shared (Read-Only) access. Multiple Shared pointers can coexist.
-    Shared,
+    Shared = 0,
     /// Mutable (Unique) access. Must be exclusive (XOR Aliasing).
-    Mutable,
-}
-
-/// A node in the Borrow Tree representing a specific pointer derivation.
-/// Tracks lineage (parent/children) and current validity (active status).
-#[derive(Debug, Clone)]
-struct Node {
-    id: usize,
-    parent: Option<usize>,
-    children: Vec<usize>,
-    permission: Perm, 
-    /// If false, this pointer has been revoked and cannot be used.
-    active: bool,
-}
-
-/// The core data structure enforcing the Aliasing Model.
-/// It maintains the hierarchy of borrows to track pointer provenance.
-struct BorrowTree {
-    nodes: Vec<Node>,
-}
-
-impl BorrowTree {
-    fn new() -> Self {
-        Self { nodes: Vec::new() }
-    }
-
-    /// Creates a new root node for a fresh allocation.
-    /// Roots are implicitly Mutable (Owners).
-    fn spawn_root(&mut self) -> usize {
-        let id = self.nodes.len();
-        self.nodes.push(Node {
-            id,
-            parent: None,
-            children: Vec::new(),
-            permission: Perm::Mutable, 
-            active: true,
-        });
-        id
-    }
-
-    /// Derives a child pointer from a valid parent.
-    /// Returns None if the parent is already invalid.
-    fn spawn_child(&mut self, parent_id: usize, perm: Perm) -> Option<usize> {
-        if parent_id >= self.nodes.len() || !self.nodes[parent_id].active {
-            return None;
+    Mutable = 1,
+    /// Shared access that may write (`&Cell<T>`, `&Mutex<T>`). Several can
+    /// coexist and write without invalidating each other.
+    SharedReadWrite = 2,
+}
+
+impl Perm {
+    /// Whether a pointer with this permission may derive one with `child`.
+    /// Permissions only narrow along a derivation: a `&T` never yields a
+    /// `&mut T`, and yields a `SharedReadWrite` pointer only inside an
+    /// interior-mutable location.
+    pub fn can_derive(self, child: Perm, interior: bool) -> bool {
+        match (self, child) {
+            (Perm::Mutable, _) | (_, Perm::Shared) => true,
+            (Perm::SharedReadWrite, Perm::SharedReadWrite) => true,
+            (Perm::Shared, Perm::SharedReadWrite) => interior,
+            (_, Perm::Mutable) => false,
         }
-        let id = self.nodes.len();
-        self.nodes.push(Node {
-            id,
-            parent: Some(parent_id),
-            children: Vec::new(),
-            permission: perm,
-            active: true,
-        });
-        self.nodes[parent_id].children.push(id);
-        Some(id)
-    }
-
-    /// Recursively invalidates a node and its entire subtree (descendants).
-    /// Uses an iterative stack approach to prevent stack overflow on deep trees.
-    fn deep_revoke(&mut self, id: usize) {
-        if id >= self.nodes.len() { return; }
-        let mut stack = vec![id];
-        while let Some(curr) = stack.pop() {
-            if let Some(node) = self.nodes.get_mut(curr) {
-                if node.active {
-                    node.active = false;
-                    stack.extend_from_slice(&node.children);
-                }
-            }
+    }
+}
+
+/// A tracked allocation: the address range it covers and its own borrows.
+struct Region {
+    base: usize,
+    size: usize,
+    /// Unique per allocation, so a reused address gets a fresh ID (see `Tag`).
+    alloc_id: u64,
+    /// The allocation's aliasing model instance. None once it has been
+    /// released, which frees every node at once.
+    borrows: Option<Box<dyn AliasingModel>>,
+}
+
+impl Region {
+    /// Whether `addr` lies inside the allocation.
+    /// Zero-sized allocations still own their base address.
+    fn contains(&self, addr: usize) -> bool {
+        addr >= self.base && addr - self.base < self.size.max(1)
+    }
+
+    /// End of the address range, exclusive.
+    fn end(&self) -> usize {
+        self.base.saturating_add(self.size.max(1))
+    }
+}
+
+/// Maps addresses to allocations.
+/// Allocations live in slots; the `ShadowIndex` backend resolves an address
+/// to the slot of the allocation covering it.
+struct ShadowMap {
+    regions: Vec<Option<Region>>,
+    /// Empty slots, reused before `regions` grows.
+    free_slots: Vec<u32>,
+    index: Box<dyn ShadowIndex>,
+    /// Interior-mutable (`UnsafeCell`) ranges, start -> end. Disjoint, and
+    /// each lies inside a single region.
+    cells: BTreeMap<usize, usize>,
+}
+
+impl ShadowMap {
+    fn new(shadow: Shadow) -> Self {
+        Self { regions: Vec::new(), free_slots: Vec::new(), index: shadow.build(), cells: BTreeMap::new() }
+    }
+
+    /// Marks `[start, end)` as interior-mutable, merging with the ranges it touches.
+    fn mark_interior_mut(&mut self, mut start: usize, mut end: usize) {
+        let touching: Vec<(usize, usize)> = self.cells
+            .range(..=end)
+            .rev()
+            .take_while(|(_, &e)| e >= start)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in touching {
+            self.cells.remove(&s);
+            start = start.min(s);
+            end = end.max(e);
         }
+        self.cells.insert(start, end);
     }
 
-    /// Revokes all children of a specific node.
-    /// This enforces "Freezing": If a parent writes, all derived pointers must be invalidated.
-    fn revoke_all_children(&mut self, id: usize) {
-        if id >= self.nodes.len() { return; }
-        
-        // Clone children list to safely iterate while modifying the tree
-        let children = self.nodes[id].children.clone();
-        for child in children {
-            self.deep_revoke(child);
+    /// Whether `addr` lies in an interior-mutable range.
+    fn is_interior_mut(&self, addr: usize) -> bool {
+        self.cells.range(..=addr).next_back().is_some_and(|(_, &end)| addr < end)
+    }
+
+    /// Records an allocation and returns the stale regions it displaced.
+    fn insert(&mut self, region: Region) -> Vec<Region> {
+        let (base, end) = (region.base, region.end());
+        let mut displaced = Vec::new();
+        for slot in self.index.overlapping(base, end) {
+            let Some(old) = self.regions[slot as usize].take() else {
+                continue;
+            };
+            self.index.unmap(old.base, old.end(), slot);
+            self.free_slots.push(slot);
+            self.clear_cells(old.base, old.end());
+            displaced.push(old);
         }
-        self.nodes[id].children.clear();
+
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.regions.push(None);
+                u32::try_from(self.regions.len() - 1).expect("[CapsLock] Too many allocations")
+            }
+        };
+        self.index.map(base, end, slot);
+        self.regions[slot as usize] = Some(region);
+        displaced
     }
 
-    /// Enforces "Aliasing XOR Mutability" for Write Access.
-    /// Invalidates all sibling nodes except the survivor (the one being used).
-    fn revoke_siblings_except(&mut self, parent_id: usize, survivor_id: usize) {
-        if parent_id >= self.nodes.len() { return; }
-        
-        let siblings = self.nodes[parent_id].children.clone();
-        for sib in siblings {
-            if sib != survivor_id {
-                self.deep_revoke(sib);
+    /// Resizes the allocation starting at `base` in place, keeping its
+    /// borrows, and returns the stale regions its new range displaced.
+    fn resize(&mut self, base: usize, size: usize) -> Vec<Region> {
+        let slot = match self.slot_of(base) {
+            Some(slot) if self.regions[slot as usize].as_ref().is_some_and(|region| region.base == base) => slot,
+            _ => return Vec::new(),
+        };
+        let mut region = self.regions[slot as usize].take().expect("checked above");
+        self.index.unmap(region.base, region.end(), slot);
+        self.free_slots.push(slot);
+
+        let old_end = region.end();
+        region.size = size;
+        // Interior-mutable ranges past the new end no longer belong to it.
+        self.clear_cells(region.end(), old_end);
+        self.insert(region)
+    }
+
+    /// Drops the freed regions overlapping `[base, end)`.
+    fn evict_freed(&mut self, base: usize, end: usize) {
+        for slot in self.index.overlapping(base, end) {
+            if let Some(old) = self.regions[slot as usize].take_if(|old| old.borrows.is_none()) {
+                self.index.unmap(old.base, old.end(), slot);
+                self.free_slots.push(slot);
+                self.clear_cells(old.base, old.end());
             }
         }
-        
-        // Update the parent's child list to reflect that only the survivor remains
-        if let Some(parent) = self.nodes.get_mut(parent_id) {
-            parent.children.retain(|&x| x == survivor_id);
-        }
     }
 
-    /// Enforces "Reader-Writer Lock" for Read Access.
-    /// Invalidates only MUTABLE siblings, allowing other Shared siblings to survive.
-    fn revoke_mutable_siblings(&mut self, parent_id: usize, survivor_id: usize) {
-        if parent_id >= self.nodes.len() { return; }
-        
-        let siblings = self.nodes[parent_id].children.clone();
-        for sib in siblings {
-            // If sibling is Mutable, it conflicts with our Shared access -> Revoke it.
-            if sib != survivor_id && self.nodes[sib].permission == Perm::Mutable {
-                self.deep_revoke(sib);
+    /// Drops every freed region.
+    fn evict_all_freed(&mut self) {
+        for slot in 0..self.regions.len() {
+            if let Some(old) = self.regions[slot].take_if(|old| old.borrows.is_none()) {
+                self.index.unmap(old.base, old.end(), slot as u32);
+                self.free_slots.push(slot as u32);
+                self.clear_cells(old.base, old.end());
             }
         }
-        // Note: We do not remove them from the parent's list here; 
-        // they are just marked inactive and filtered out during validity checks.
     }
 
-    /// Checks if a pointer is valid by verifying the entire path to the root.
-    fn is_valid(&self, id: usize) -> bool {
-        let mut curr = Some(id);
-        while let Some(idx) = curr {
-            match self.nodes.get(idx) {
-                Some(node) => {
-                    if !node.active { return false; }
-                    curr = node.parent;
-                }
-                None => return false,
-            }
+    /// Unmarks `[start, end)`, cutting short a range that straddles `start`.
+    fn clear_cells(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let inside: Vec<usize> = self.cells.range(start..end).map(|(s, _)| *s).collect();
+        for s in inside {
+            self.cells.remove(&s);
+        }
+        if let Some((_, e)) = self.cells.range_mut(..start).next_back() {
+            *e = (*e).min(start);
         }
-        true
     }
 
-    fn get_perm(&self, id: usize) -> Perm {
-        self.nodes[id].permission
+    /// The interior-mutable ranges inside `[start, end)`.
+    fn cells_in(&self, start: usize, end: usize) -> Vec<(usize, usize)> {
+        self.cells.range(start..end).map(|(&s, &e)| (s, e)).collect()
     }
 
-    fn get_parent(&self, id: usize) -> Option<usize> {
-        self.nodes[id].parent
+    /// The allocation starting at `base`.
+    fn get(&self, base: usize) -> Option<&Region> {
+        self.find(base).filter(|region| region.base == base)
     }
+
+    /// The slot of the allocation containing `addr`, if any.
+    fn slot_of(&self, addr: usize) -> Option<u32> {
+        let slot = self.index.lookup(addr)?;
+        match self.regions.get(slot as usize) {
+            Some(Some(region)) if region.contains(addr) => Some(slot),
+            _ => self.index.resolve(addr),
+        }
+    }
+
+    /// Finds the allocation containing `addr`, if any.
+    fn find(&self, addr: usize) -> Option<&Region> {
+        self.regions[self.slot_of(addr)? as usize].as_ref()
+    }
+
+    fn find_mut(&mut self, addr: usize) -> Option<&mut Region> {
+        let slot = self.slot_of(addr)?;
+        self.regions[slot as usize].as_mut()
+    }
+}
+
+/// The kind of memory operation being validated.
+/// Revocation follows the operation, not the permission of the pointer used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Opaque handle to a node of the aliasing model (a derived pointer).
+/// Every tracked pointer carries its own Tag, so several borrows of the same
+/// address (e.g. a `&T` and a `&mut T`) are told apart by Tag, not by address.
+///
+/// It holds the ID of the allocation the pointer was derived from and the
+/// node's ID in that allocation's model. A pointer into a previous
+/// allocation at a reused address therefore never matches the current one
+/// (no ABA confusion). Both are 64 bits wide, so neither runs out in a
+/// long-running process. The layout is part of the C ABI (`capslock_tag`).
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tag {
+    alloc_id: u64,
+    id: u64,
+}
+
+impl fmt::Debug for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Tag::INVALID {
+            write!(f, "Tag(INVALID)")
+        } else {
+            write!(f, "Tag(#{}:{})", self.alloc_id(), self.id())
+        }
+    }
+}
+
+impl Tag {
+    /// Handed out when a non-fatal Policy lets a rejected reborrow continue.
+    /// It belongs to no allocation, so every use of it is reported again.
+    pub const INVALID: Tag = Tag { alloc_id: u64::MAX, id: u64::MAX };
+
+    fn new(alloc_id: u64, id: usize) -> Self {
+        Tag { alloc_id, id: id as u64 }
+    }
+
+    fn id(self) -> usize {
+        self.id as usize
+    }
+
+    /// The ID of the allocation this Tag was derived from.
+    pub fn alloc_id(self) -> u64 {
+        self.alloc_id
+    }
+}
+
+/// Number of Replaced allocation IDs a Runtime remembers (see `Runtime::replaced`).
+const REPLACED_CAPACITY: usize = 4096;
+
+/// Why an allocation stopped being live.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Retired {
+    /// Released through `handle_free`.
+    Freed,
+    /// Its address range was claimed by a newer allocation without a free.
+    Replaced,
 }
 
 /// The Runtime Monitor state.
-/// Holds the BorrowTree (Logical Model) and Shadow Map (Address -> Tree Node mapping).
+/// Holds the Shadow Map (Address range -> Allocation and its Aliasing Model instance).
 pub struct Runtime {
-    tree: BorrowTree,
-    shadow_map: HashMap<usize, usize>,
+    /// Aliasing model of new allocations: its name, and the constructor of
+    /// each allocation's instance.
+    model_name: &'static str,
+    build_model: fn() -> Box<dyn AliasingModel>,
+    shadow_map: ShadowMap,
+    /// Base address of each live allocation, by ID. Every other ID below
+    /// `next_alloc_id` is retired; this outlives the shadow entry, so stale
+    /// Tags are still recognised once the address is reused.
+    live: HashMap<u64, usize>,
+    /// The last `REPLACED_CAPACITY` IDs retired as Replaced, oldest first;
+    /// every other retired ID counts as Freed. Replacing a live allocation is
+    /// the rare case, so freed ones cost nothing. Bounded, since a program
+    /// may reuse addresses without ever freeing them: Tags of an allocation
+    /// replaced longer ago are reported as Use-After-Free instead.
+    replaced: VecDeque<u64>,
+    next_alloc_id: u64,
+    /// None follows the process-wide Policy (`set_policy`).
+    policy: Option<Policy>,
+    /// Number of violations handed to the Policy so far.
+    violations: usize,
 }
 
 thread_local! {
     /// Thread-local singleton for the runtime monitor.
-    pub static RT: RefCell<Runtime> = RefCell::new(Runtime::new());
+    pub static RT: RefCell<Runtime> = RefCell::new(Runtime::from_env());
+
+    /// Set while this thread holds `GLOBAL_RT`, so re-entrant calls fail instead of deadlocking.
+    static IN_GLOBAL_RT: Cell<bool> = const { Cell::new(false) };
+}
+
+lazy_static! {
+    /// Process-wide runtime monitor, shared by every thread.
+    /// Pointers handed between threads are validated against the same aliasing model state.
+    pub static ref GLOBAL_RT: Mutex<Runtime> = Mutex::new(Runtime::from_env());
+}
+
+/// Which runtime instance the public API and the C API operate on.
+/// Selected with `set_scope` or the `CAPSLOCK_SCOPE` environment variable
+/// (`thread` or `process`); choose it before tracking anything, since the
+/// two runtimes do not share state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// One runtime per thread (`RT`, default). No locking, but cross-thread
+    /// accesses are invisible.
+    Thread,
+    /// One runtime for the whole process (`GLOBAL_RT`), behind a lock.
+    Process,
+}
+
+impl EnvSetting for Scope {
+    const ENV_VAR: &'static str = "CAPSLOCK_SCOPE";
+    const DEFAULT: Self = Scope::Thread;
+    const VALUES: &'static [(&'static str, Self)] = &[("thread", Scope::Thread), ("process", Scope::Process)];
+}
+
+/// 0 = not yet read from the environment, 1 = Thread, 2 = Process.
+static SCOPE: AtomicU8 = AtomicU8::new(0);
+
+pub fn scope() -> Scope {
+    match SCOPE.load(Ordering::Relaxed) {
+        1 => Scope::Thread,
+        2 => Scope::Process,
+        _ => {
+            let scope = Scope::from_env();
+            // Lose gracefully to a concurrent `set_scope`.
+            let _ = SCOPE.compare_exchange(0, scope as u8 + 1, Ordering::Relaxed, Ordering::Relaxed);
+            self::scope()
+        }
+    }
+}
+
+pub fn set_scope(scope: Scope) {
+    SCOPE.store(scope as u8 + 1, Ordering::Relaxed);
+}
+
+/// 0 = not yet read from the environment, otherwise the Policy plus one.
+/// Shared by every thread and both scopes.
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// The process-wide Policy, initially from `CAPSLOCK_MODE`.
+pub fn policy() -> Policy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => Policy::Panic,
+        2 => Policy::Abort,
+        3 => Policy::Log,
+        4 => Policy::Count,
+        _ => {
+            let policy = Policy::from_env();
+            // Lose gracefully to a concurrent `set_policy`.
+            let _ = POLICY.compare_exchange(0, policy as u8 + 1, Ordering::Relaxed, Ordering::Relaxed);
+            self::policy()
+        }
+    }
+}
+
+/// Selects the Policy of every thread, whatever the scope.
+pub fn set_policy(policy: Policy) {
+    POLICY.store(policy as u8 + 1, Ordering::Relaxed);
+}
+
+/// Clears the re-entrancy flag even if `f` unwinds.
+struct GlobalGuard;
+
+impl Drop for GlobalGuard {
+    fn drop(&mut self) {
+        let _ = IN_GLOBAL_RT.try_with(|flag| flag.set(false));
+    }
+}
+
+/// Runs `f` on the runtime selected by `scope()`.
+/// Returns None if it is unavailable: a re-entrant call, or thread teardown
+/// for the thread-local runtime.
+pub fn try_with_runtime<R>(f: impl FnOnce(&mut Runtime) -> R) -> Option<R> {
+    try_with_scope(scope(), f)
+}
+
+/// Like `try_with_runtime`, on the runtime of `scope` whatever is selected.
+pub(crate) fn try_with_scope<R>(scope: Scope, f: impl FnOnce(&mut Runtime) -> R) -> Option<R> {
+    match scope {
+        Scope::Thread => RT
+            .try_with(|rt| rt.try_borrow_mut().ok().map(|mut rt| f(&mut rt)))
+            .ok()
+            .flatten(),
+        Scope::Process => {
+            if IN_GLOBAL_RT.try_with(|flag| flag.replace(true)).unwrap_or(false) {
+                return None;
+            }
+            let _guard = GlobalGuard;
+            let mut rt = GLOBAL_RT.lock().unwrap_or_else(PoisonError::into_inner);
+            Some(f(&mut rt))
+        }
+    }
+}
+
+/// Like `try_with_runtime`, panicking if the runtime is unavailable.
+fn with_runtime<R>(f: impl FnOnce(&mut Runtime) -> R) -> R {
+    try_with_runtime(f).expect("[CapsLock] Runtime is unavailable (re-entrant call?)")
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Runtime {
     pub fn new() -> Self {
+        Self::with_policy(Policy::Panic)
+    }
+
+    /// Model and shadow backend from `CAPSLOCK_MODEL` and `CAPSLOCK_SHADOW`.
+    /// Follows the process-wide Policy (`policy()`).
+    pub fn from_env() -> Self {
+        Self::with_shadow(Model::from_env(), Shadow::from_env())
+    }
+
+    pub fn with_policy(policy: Policy) -> Self {
+        let mut rt = Self::with_model(Model::TreeBorrows);
+        rt.set_policy(policy);
+        rt
+    }
+
+    pub fn with_model(model: Model) -> Self {
+        Self::with_shadow(model, Shadow::Ranges)
+    }
+
+    pub fn with_shadow(model: Model, shadow: Shadow) -> Self {
+        Self::with_model_parts(model.name(), model.constructor(), shadow)
+    }
+
+    /// Uses an aliasing model defined outside this crate, e.g. a baseline to
+    /// compare the built-in ones against. `build` creates the instance of
+    /// each new allocation; `name` is reported as the model's name.
+    pub fn with_custom_model(name: &'static str, build: fn() -> Box<dyn AliasingModel>, shadow: Shadow) -> Self {
+        Self::with_model_parts(name, build, shadow)
+    }
+
+    fn with_model_parts(model_name: &'static str, build_model: fn() -> Box<dyn AliasingModel>, shadow: Shadow) -> Self {
         Self {
-            tree: BorrowTree::new(),
-            shadow_map: HashMap::new(),
+            model_name,
+            build_model,
+            shadow_map: ShadowMap::new(shadow),
+            live: HashMap::new(),
+            replaced: VecDeque::new(),
+            next_alloc_id: 0,
+            policy: None,
+            violations: 0,
+        }
+    }
+
+    /// Name of the aliasing model new allocations use.
+    pub fn model_name(&self) -> &'static str {
+        self.model_name
+    }
+
+    /// Name of the shadow backend resolving addresses.
+    pub fn shadow_name(&self) -> &'static str {
+        self.shadow_map.index.name()
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy.unwrap_or_else(policy)
+    }
+
+    /// Gives this runtime its own Policy, instead of the process-wide one.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = Some(policy);
+    }
+
+    /// Number of violations reported through `report` so far.
+    pub fn violation_count(&self) -> usize {
+        self.violations
+    }
+
+    /// Applies the Policy to a detected violation.
+    /// Returns the violation back only when the caller has to panic with it,
+    /// so the panic can be raised after the runtime is released.
+    pub fn report(&mut self, violation: Violation) -> Option<Violation> {
+        self.violations += 1;
+        match self.policy() {
+            Policy::Panic => Some(violation),
+            Policy::Abort => {
+                eprintln!("{}", violation);
+                std::process::abort();
+            }
+            Policy::Log => {
+                eprintln!("{}", violation);
+                None
+            }
+            Policy::Count => None,
+        }
+    }
+
+    /// Tracks a new memory allocation (Root) of `size` bytes and returns the owner's Tag.
+    /// Live allocations it overlaps were never freed; they are retired as
+    /// Replaced, so their Tags cannot pass as Tags of the new allocation.
+    pub fn handle_alloc(&mut self, addr: usize, size: usize) -> Tag {
+        let alloc_id = self.next_alloc_id;
+        assert!(alloc_id != Tag::INVALID.alloc_id(), "[CapsLock] Allocation IDs exhausted");
+        self.next_alloc_id += 1;
+        self.live.insert(alloc_id, addr);
+
+        let borrows = Some((self.build_model)());
+        let displaced = self.shadow_map.insert(Region { base: addr, size, alloc_id, borrows });
+        self.retire_displaced(displaced);
+        Tag::new(alloc_id, ROOT)
+    }
+
+    /// Tracks a `realloc` of the allocation starting at `old` to `new_size`
+    /// bytes at `new`, and returns the owner's Tag of the result.
+    /// Resized in place (`new == old`), it keeps its Tags and borrows.
+    /// Moved, the old block is freed, so every Tag derived from it is
+    /// reported as Use-After-Free, and `new` becomes a fresh allocation with
+    /// the same interior-mutable ranges. An `old` inside an allocation
+    /// but not at its start is reported as an invalid free; an untracked
+    /// one only registers `new` as a fresh allocation.
+    pub fn handle_realloc(&mut self, old: usize, new: usize, new_size: usize) -> Result<Tag, Violation> {
+        let (alloc_id, end) = match self.realloc_source(old)? {
+            Some(region) => (region.alloc_id, region.end()),
+            None => return Ok(self.handle_alloc(new, new_size)),
+        };
+        if new == old {
+            let displaced = self.shadow_map.resize(old, new_size);
+            self.retire_displaced(displaced);
+            return Ok(Tag::new(alloc_id, ROOT));
+        }
+
+        let cells = self.shadow_map.cells_in(old, end);
+        self.handle_free(old)?;
+        let tag = self.handle_alloc(new, new_size);
+        for (start, end) in cells {
+            self.handle_interior_mut(new + (start - old), end - start);
+        }
+        Ok(tag)
+    }
+
+    /// Checks, without changing anything, that `old` may be reallocated:
+    /// the start of a live allocation, or untracked. Lets an allocator
+    /// refuse a `realloc` that `handle_realloc` would reject before the
+    /// block ever reaches it.
+    pub fn check_realloc(&self, old: usize) -> Result<(), Violation> {
+        self.realloc_source(old).map(drop)
+    }
+
+    /// The live allocation starting at `old`, or None if `old` is untracked.
+    fn realloc_source(&self, old: usize) -> Result<Option<&Region>, Violation> {
+        match self.shadow_map.find(old) {
+            Some(Region { borrows: None, .. }) => Err(Violation::DoubleFree { addr: old }),
+            Some(region) if region.base != old => Err(Violation::InvalidFree { addr: old, base: region.base }),
+            Some(Region { alloc_id, borrows: Some(borrows), .. }) => {
+                check_unprotected(old, *alloc_id, borrows.as_ref())?;
+                Ok(self.shadow_map.find(old))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Forgets the freed allocations overlapping `size` bytes at `addr`,
+    /// which the allocator handed out again without the runtime tracking
+    /// it (e.g. to the runtime itself), so releasing them later is not
+    /// reported as a double free.
+    pub(crate) fn handle_reuse(&mut self, addr: usize, size: usize) {
+        self.shadow_map.evict_freed(addr, addr.saturating_add(size.max(1)));
+    }
+
+    /// Like `handle_reuse`, for every freed allocation, when the reused
+    /// blocks were not recorded.
+    pub(crate) fn handle_reuse_all(&mut self) {
+        self.shadow_map.evict_all_freed();
+    }
+
+    /// Retires the live allocations a new range claimed without a free.
+    fn retire_displaced(&mut self, displaced: Vec<Region>) {
+        for old in displaced.into_iter().filter(|old| old.borrows.is_some()) {
+            self.retire(old.alloc_id, Retired::Replaced);
         }
     }
 
-    /// Tracks a new memory allocation (Root).
-    pub fn handle_alloc(&mut self, addr: usize) {
-        let root_id = self.tree.spawn_root();
-        self.shadow_map.insert(addr, root_id);
+    /// The owner's Tag of the live allocation containing `addr`, e.g. one
+    /// registered by `CapslockAllocator`.
+    pub fn owner_tag(&self, addr: usize) -> Option<Tag> {
+        match self.shadow_map.find(addr) {
+            Some(Region { alloc_id, borrows: Some(_), .. }) => Some(Tag::new(*alloc_id, ROOT)),
+            _ => None,
+        }
+    }
+
+    /// The still-usable borrows of the allocation containing `addr`, owner first.
+    pub fn live_borrows(&self, addr: usize) -> Vec<Tag> {
+        match self.shadow_map.find(addr) {
+            Some(Region { alloc_id, borrows: Some(borrows), .. }) => {
+                borrows.live_nodes().into_iter().map(|id| Tag::new(*alloc_id, id)).collect()
+            }
+            _ => Vec::new(),
+        }
     }
 
     /// Tracks a reborrow (derivation of a new pointer from an existing one).
-    /// Implements Lazy Revocation: No invalidation happens here, only tree insertion.
-    pub fn handle_reborrow(&mut self, parent_addr: usize, new_addr: usize, perm: Perm) {
-        let parent_id = match self.shadow_map.get(&parent_addr) {
-            Some(id) => *id, 
-            None => panic!("[Security] Reborrow from untracked address 0x{:x}", parent_addr),
+    /// The model decides what the reborrow itself invalidates (e.g. Tree Borrows
+    /// only performs a read through the parent).
+    /// A reborrow that widens the parent's permission is reported as
+    /// `PermissionEscalation` before the model sees it, so it derives
+    /// nothing and invalidates nothing.
+    pub fn handle_reborrow(&mut self, parent_addr: usize, parent_tag: Tag, perm: Perm) -> Result<Tag, Violation> {
+        self.check_live(parent_addr, parent_tag)?;
+        let interior = self.shadow_map.is_interior_mut(parent_addr);
+
+        let borrows = match self.borrows_at(parent_addr, parent_tag)? {
+            Some(borrows) => borrows,
+            None => return Err(Violation::UntrackedReborrow { addr: parent_addr, tag: parent_tag }),
         };
 
-        match self.tree.spawn_child(parent_id, perm) {
-            Some(child_id) => {
-                self.shadow_map.insert(new_addr, child_id);
+        let parent_id = parent_tag.id();
+        // A parent the model forgot is left to `on_retag` to reject.
+        match borrows.perm(parent_id) {
+            Some(parent_perm) if !parent_perm.can_derive(perm, interior) => {
+                return Err(Violation::PermissionEscalation {
+                    addr: parent_addr,
+                    tag: parent_tag,
+                    parent_perm,
+                    perm,
+                    lineage: lineage(borrows, parent_tag),
+                });
             }
-            None => panic!("[Security] Parent at 0x{:x} is already invalidated.", parent_addr),
+            _ => {}
         }
+        let child_id = borrows.on_retag(parent_id, perm).map_err(|fault| match fault {
+            Fault::Protected(node) => Violation::ProtectedRevoke {
+                addr: parent_addr,
+                tag: parent_tag,
+                protected: Tag::new(parent_tag.alloc_id(), node),
+                lineage: lineage(borrows, parent_tag),
+            },
+            _ => Violation::ReborrowFromInvalid {
+                addr: parent_addr,
+                tag: parent_tag,
+                perm: borrows.perm(parent_id),
+                lineage: lineage(borrows, parent_tag),
+            },
+        })?;
+        Ok(Tag::new(parent_tag.alloc_id(), child_id))
     }
 
-    /// Validates access and triggers Revoke-on-Use logic.
+    /// Validates a read of `len` bytes through (`addr`, `tag`).
+    pub fn handle_read(&mut self, addr: usize, len: usize, tag: Tag) -> Result<(), Violation> {
+        self.handle_access(addr, len, tag, Access::Read)
+    }
+
+    /// Validates a write of `len` bytes through (`addr`, `tag`).
+    pub fn handle_write(&mut self, addr: usize, len: usize, tag: Tag) -> Result<(), Violation> {
+        self.handle_access(addr, len, tag, Access::Write)
+    }
+
+    /// Validates access and lets the aliasing model apply it.
     /// This function acts as the Reference Monitor barrier.
-    pub fn handle_access(&mut self, addr: usize) {
-        let id = match self.shadow_map.get(&addr) {
-            Some(id) => *id, 
-            None => return, // Ignore untracked memory (e.g., stack vars not monitored)
+    pub fn handle_access(&mut self, addr: usize, len: usize, tag: Tag, access: Access) -> Result<(), Violation> {
+        self.check_live(addr, tag)?;
+        self.check_bounds(addr, len, tag)?;
+        let interior = self.shadow_map.is_interior_mut(addr);
+
+        // 1. Validate Provenance (Was the Tag derived from this allocation?)
+        let borrows = match self.borrows_at(addr, tag)? {
+            Some(borrows) => borrows,
+            None => return Ok(()), // Ignore untracked memory (e.g., stack vars not monitored)
         };
+        let id = tag.id();
 
-        // 1. Validate Provenance (Is the pointer still alive?)
-        if !self.tree.is_valid(id) {
-            panic!("[Security Violation] Use-After-Free/Revocation at 0x{:x}", addr);
+        // 2. Apply the access to the allocation's aliasing model.
+        let outcome = match access {
+            Access::Read => borrows.on_read(id),
+            Access::Write => borrows.on_write(id, interior),
+        };
+        outcome.map_err(|fault| match fault {
+            Fault::ReadOnly => Violation::WriteThroughShared { addr, tag, lineage: lineage(borrows, tag) },
+            Fault::Revoked => Violation::UseAfterRevoke {
+                addr,
+                tag,
+                perm: borrows.perm(id),
+                lineage: lineage(borrows, tag),
+            },
+            Fault::Protected(node) => Violation::ProtectedRevoke {
+                addr,
+                tag,
+                protected: Tag::new(tag.alloc_id(), node),
+                lineage: lineage(borrows, tag),
+            },
+        })
+    }
+
+    /// Ends the borrow (`addr`, `tag`): it and every pointer derived from it
+    /// become invalid, and stop taking part in the revocation of their
+    /// siblings. Releasing a pointer that was already revoked is not a violation.
+    pub fn handle_release(&mut self, addr: usize, tag: Tag) -> Result<(), Violation> {
+        self.check_live(addr, tag)?;
+
+        let borrows = match self.borrows_at(addr, tag)? {
+            Some(borrows) => borrows,
+            None => return Ok(()),
+        };
+        match borrows.on_release(tag.id()) {
+            Err(Fault::Protected(node)) => Err(Violation::ProtectedRevoke {
+                addr,
+                tag,
+                protected: Tag::new(tag.alloc_id(), node),
+                lineage: lineage(borrows, tag),
+            }),
+            _ => Ok(()),
         }
+    }
+
+    /// Protects (`addr`, `tag`) until the matching `handle_unprotect`, e.g. for
+    /// the duration of a call it was passed into. Any access that would
+    /// invalidate it in the meantime is reported as `ProtectedRevoke`.
+    pub fn handle_protect(&mut self, addr: usize, tag: Tag) -> Result<(), Violation> {
+        self.check_live(addr, tag)?;
 
-        let perm = self.tree.get_perm(id);
+        let borrows = match self.borrows_at(addr, tag)? {
+            Some(borrows) => borrows,
+            None => return Ok(()), // Nothing to protect in untracked memory
+        };
+        let id = tag.id();
+        borrows.protect(id).map_err(|_| Violation::UseAfterRevoke {
+            addr,
+            tag,
+            perm: borrows.perm(id),
+            lineage: lineage(borrows, tag),
+        })
+    }
 
-        // 2. Vertical Enforcement: Writer kills Children (Freezing)
-        // If we are Writing, we assert uniqueness, so all derived pointers must die.
-        if perm == Perm::Mutable {
-            self.tree.revoke_all_children(id);
+    /// Releases one protector added by `handle_protect`.
+    /// Does nothing once the allocation is gone.
+    pub fn handle_unprotect(&mut self, tag: Tag) {
+        if let Some(borrows) = self.live_borrows_of(tag.alloc_id()) {
+            borrows.unprotect(tag.id());
         }
+    }
+
+    /// Marks `size` bytes at `addr` as interior-mutable (the contents of an
+    /// `UnsafeCell`): writes there through shared pointers are allowed and
+    /// leave sibling readers valid. The range is clipped to the allocation
+    /// containing `addr`; untracked addresses are ignored.
+    pub fn handle_interior_mut(&mut self, addr: usize, size: usize) {
+        let region = match self.shadow_map.find(addr) {
+            Some(region) => region,
+            None => return,
+        };
+        let end = addr.saturating_add(size.max(1)).min(region.end());
+        self.shadow_map.mark_interior_mut(addr, end);
+    }
 
-        // 3. Horizontal Enforcement: Sibling Conflicts
-        if let Some(parent) = self.tree.get_parent(id) {
-            if perm == Perm::Mutable {
-                // WRITE Access: Exclusive. Kill ALL siblings.
-                self.tree.revoke_siblings_except(parent, id);
-            } else if perm == Perm::Shared {
-                // READ Access: Shared. Kill only MUTABLE siblings.
-                self.tree.revoke_mutable_siblings(parent, id);
+    /// Revokes the whole allocation containing `addr`, root included.
+    /// Called when foreign code reports that it has written to or released the memory.
+    /// Fails, leaving the allocation untouched, while one of its pointers is
+    /// protected.
+    pub fn handle_revoke(&mut self, addr: usize) -> Result<(), Violation> {
+        let Some(region) = self.shadow_map.find_mut(addr) else { return Ok(()) };
+        let alloc_id = region.alloc_id;
+        if let Some(borrows) = region.borrows.as_mut() {
+            check_unprotected(addr, alloc_id, borrows.as_ref())?;
+            borrows.on_revoke();
+        }
+        Ok(())
+    }
+
+    /// Tracks the release of an allocation.
+    /// Its borrows are dropped at once, and any later use of its Tags is
+    /// reported as Use-After-Free, even after the address is reused.
+    /// The shadow entry is kept until then so a second release is caught.
+    /// Releasing an address inside an allocation, but not at its start, is
+    /// reported as an invalid free and leaves the allocation live, and so
+    /// is releasing an allocation while one of its pointers is protected.
+    pub fn handle_free(&mut self, addr: usize) -> Result<(), Violation> {
+        let alloc_id = match self.shadow_map.find_mut(addr) {
+            Some(Region { borrows: None, .. }) => return Err(Violation::DoubleFree { addr }),
+            Some(region) if region.base != addr => return Err(Violation::InvalidFree { addr, base: region.base }),
+            Some(Region { alloc_id, borrows: borrows @ Some(_), .. }) => {
+                check_unprotected(addr, *alloc_id, borrows.as_deref().expect("matched Some"))?;
+                *borrows = None;
+                *alloc_id
+            }
+            None => return Ok(()), // Foreign code may release memory we never tracked
+        };
+        self.retire(alloc_id, Retired::Freed);
+        Ok(())
+    }
+
+    fn retire(&mut self, alloc_id: u64, how: Retired) {
+        self.live.remove(&alloc_id);
+        if how == Retired::Replaced {
+            if self.replaced.len() == REPLACED_CAPACITY {
+                self.replaced.pop_front();
+            }
+            self.replaced.push_back(alloc_id);
+        }
+    }
+
+    /// Why the allocation `alloc_id` is no longer live, if it is not.
+    /// IDs that were never handed out (e.g. `Tag::INVALID`'s) count as live.
+    fn retired(&self, alloc_id: u64) -> Option<Retired> {
+        if alloc_id >= self.next_alloc_id || self.live.contains_key(&alloc_id) {
+            return None;
+        }
+        if self.replaced.contains(&alloc_id) {
+            Some(Retired::Replaced)
+        } else {
+            Some(Retired::Freed)
+        }
+    }
+
+    /// Rejects Tags whose allocation is no longer live.
+    /// Only needs the allocation ID carried by the Tag, not the address.
+    fn check_live(&self, addr: usize, tag: Tag) -> Result<(), Violation> {
+        // The common case needs no lookup by ID: the Tag's allocation still
+        // covers the address.
+        if self.shadow_map.find(addr).is_some_and(|region| region.alloc_id == tag.alloc_id() && region.borrows.is_some()) {
+            return Ok(());
+        }
+        match self.retired(tag.alloc_id()) {
+            None => Ok(()),
+            Some(Retired::Freed) => Err(Violation::UseAfterFree { addr, tag, lineage: self.lineage(tag) }),
+            Some(Retired::Replaced) => Err(Violation::StaleAllocation { addr, tag, lineage: self.lineage(tag) }),
+        }
+    }
+
+    /// Spatial check: `[addr, addr + len)` must lie inside the allocation
+    /// `tag` was derived from. Tags of no live allocation are left to the
+    /// temporal and provenance checks.
+    fn check_bounds(&self, addr: usize, len: usize, tag: Tag) -> Result<(), Violation> {
+        // As in `check_live`, the allocation at the address is usually the Tag's.
+        let region = match self.shadow_map.find(addr).filter(|region| region.alloc_id == tag.alloc_id()) {
+            Some(region) => region,
+            None => match self.live.get(&tag.alloc_id()).and_then(|&base| self.shadow_map.get(base)) {
+                Some(region) => region,
+                None => return Ok(()),
+            },
+        };
+        // Not `region.end()`, which gives empty allocations a byte to be found by.
+        let end = region.base.saturating_add(region.size);
+        let inside = addr >= region.base && addr.checked_add(len).is_some_and(|access_end| access_end <= end);
+        if inside {
+            return Ok(());
+        }
+        Err(Violation::OutOfBounds {
+            addr,
+            len,
+            tag,
+            base: region.base,
+            size: region.size,
+            lineage: self.lineage(tag),
+        })
+    }
+
+    /// The model instance of a live allocation, by ID.
+    fn live_borrows_of(&mut self, alloc_id: u64) -> Option<&mut (dyn AliasingModel + 'static)> {
+        let base = *self.live.get(&alloc_id)?;
+        self.shadow_map.find_mut(base)?.borrows.as_deref_mut()
+    }
+
+    /// The model instance of the allocation containing `addr`, once `tag` is
+    /// known to be derived from it (provenance). None for untracked memory.
+    fn borrows_at(&mut self, addr: usize, tag: Tag) -> Result<Option<&mut (dyn AliasingModel + 'static)>, Violation> {
+        let derived = match self.shadow_map.find(addr) {
+            Some(region) => {
+                tag.alloc_id() == region.alloc_id
+                    && region.borrows.as_ref().is_some_and(|borrows| borrows.derived(tag.id()))
             }
+            None => return Ok(None),
+        };
+        if !derived {
+            return Err(Violation::ForeignTag { addr, tag, lineage: self.lineage(tag) });
+        }
+        Ok(self.shadow_map.find_mut(addr).and_then(|region| region.borrows.as_deref_mut()))
+    }
+
+    /// The lineage of a Tag's node as Tags, for violation reports.
+    /// Empty once its allocation is gone.
+    fn lineage(&self, tag: Tag) -> Vec<Tag> {
+        self.live
+            .get(&tag.alloc_id())
+            .and_then(|&base| self.shadow_map.get(base))
+            .and_then(|region| region.borrows.as_deref())
+            .map(|borrows| lineage(borrows, tag))
+            .unwrap_or_default()
+    }
+}
+
+/// Rejects ending the allocation `alloc_id` as a whole (a free, realloc or
+/// revocation) while one of its pointers is protected. Reported against
+/// the owner's Tag.
+fn check_unprotected(addr: usize, alloc_id: u64, borrows: &dyn AliasingModel) -> Result<(), Violation> {
+    match borrows.protected() {
+        Some(node) => {
+            let owner = Tag::new(alloc_id, ROOT);
+            Err(Violation::ProtectedRevoke {
+                addr,
+                tag: owner,
+                protected: Tag::new(alloc_id, node),
+                lineage: lineage(borrows, owner),
+            })
         }
+        None => Ok(()),
     }
 }
 
+/// The lineage of `tag` in its allocation's model instance, as Tags.
+fn lineage(borrows: &dyn AliasingModel, tag: Tag) -> Vec<Tag> {
+    borrows.lineage(tag.id()).into_iter().map(|id| Tag::new(tag.alloc_id(), id)).collect()
+}
+
 // --- Public API (Exposed to FFI / Instrumentation) ---
+// The `try_*` variants return violations to the caller untouched;
+// the others hand them to the runtime's Policy.
+
+pub fn violation_count() -> usize {
+    with_runtime(|rt| rt.violation_count())
+}
+
+/// Runs `f` on the selected runtime and applies the Policy to a violation.
+/// Non-fatal policies continue with `fallback`.
+fn enforce<R>(fallback: R, f: impl FnOnce(&mut Runtime) -> Result<R, Violation>) -> R {
+    let outcome = with_runtime(|rt| f(rt).map_err(|v| rt.report(v)));
+
+    match outcome {
+        Ok(value) => value,
+        Err(Some(violation)) => panic!("{}", violation),
+        Err(None) => fallback,
+    }
+}
+
+/// The still-usable borrows of the allocation containing `ptr`, owner first.
+pub fn live_borrows<T>(ptr: *const T) -> Vec<Tag> {
+    with_runtime(|rt| rt.live_borrows(ptr as usize))
+}
+
+/// The owner's Tag of the live allocation containing `ptr`.
+pub fn owner_tag<T>(ptr: *const T) -> Option<Tag> {
+    with_runtime(|rt| rt.owner_tag(ptr as usize))
+}
+
+pub fn track_alloc<T>(ptr: *const T) -> Tag {
+    track_alloc_bytes(ptr, std::mem::size_of::<T>())
+}
+
+/// Like `track_alloc`, for allocations whose size is not `size_of::<T>()` (arrays, buffers).
+pub fn track_alloc_bytes<T>(ptr: *const T, size: usize) -> Tag {
+    with_runtime(|rt| rt.handle_alloc(ptr as usize, size))
+}
+
+/// Marks the `T` at `ptr` as interior-mutable (e.g. a `Cell<T>` field).
+pub fn track_interior_mut<T>(ptr: *const T) {
+    track_interior_mut_bytes(ptr, std::mem::size_of::<T>())
+}
+
+/// Like `track_interior_mut`, for ranges whose size is not `size_of::<T>()`.
+pub fn track_interior_mut_bytes<T>(ptr: *const T, size: usize) {
+    with_runtime(|rt| rt.handle_interior_mut(ptr as usize, size))
+}
+
+pub fn try_track_realloc<T, U>(old: *const T, new: *const U, new_size: usize) -> Result<Tag, Violation> {
+    with_runtime(|rt| rt.handle_realloc(old as usize, new as usize, new_size))
+}
+
+pub fn try_track_free<T>(ptr: *const T) -> Result<(), Violation> {
+    with_runtime(|rt| rt.handle_free(ptr as usize))
+}
+
+pub fn try_track_borrow<T>(parent: *const T, parent_tag: Tag, perm: Perm) -> Result<Tag, Violation> {
+    with_runtime(|rt| rt.handle_reborrow(parent as usize, parent_tag, perm))
+}
+
+pub fn try_check_read<T>(ptr: *const T, tag: Tag) -> Result<(), Violation> {
+    try_check_read_bytes(ptr, std::mem::size_of::<T>(), tag)
+}
+
+pub fn try_check_write<T>(ptr: *const T, tag: Tag) -> Result<(), Violation> {
+    try_check_write_bytes(ptr, std::mem::size_of::<T>(), tag)
+}
+
+/// Like `try_check_read`, for accesses of `len` bytes (slices, buffers).
+pub fn try_check_read_bytes<T>(ptr: *const T, len: usize, tag: Tag) -> Result<(), Violation> {
+    with_runtime(|rt| rt.handle_read(ptr as usize, len, tag))
+}
+
+/// Like `try_check_write`, for accesses of `len` bytes (slices, buffers).
+pub fn try_check_write_bytes<T>(ptr: *const T, len: usize, tag: Tag) -> Result<(), Violation> {
+    with_runtime(|rt| rt.handle_write(ptr as usize, len, tag))
+}
+
+/// A tracked borrow that is released when it goes out of scope
+/// (see `track_borrow_scoped`).
+pub struct ScopedBorrow {
+    addr: usize,
+    tag: Tag,
+}
+
+impl ScopedBorrow {
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+}
+
+impl Drop for ScopedBorrow {
+    fn drop(&mut self) {
+        if self.tag == Tag::INVALID {
+            return;
+        }
+        // The runtime may already be gone during thread teardown.
+        let outcome = try_with_runtime(|rt| rt.handle_release(self.addr, self.tag).map_err(|v| rt.report(v)));
+        if let Some(Err(Some(violation))) = outcome {
+            if !std::thread::panicking() {
+                panic!("{}", violation);
+            }
+        }
+    }
+}
+
+/// Keeps a pointer protected while in scope (see `protect`).
+pub struct Protector {
+    tag: Tag,
+}
+
+impl Drop for Protector {
+    fn drop(&mut self) {
+        // The runtime may already be gone during thread teardown.
+        let _ = try_with_runtime(|rt| rt.handle_unprotect(self.tag));
+    }
+}
+
+pub fn try_protect<T>(ptr: *const T, tag: Tag) -> Result<Protector, Violation> {
+    with_runtime(|rt| rt.handle_protect(ptr as usize, tag)).map(|()| Protector { tag })
+}
 
-pub fn track_alloc<T>(ptr: *const T) {
-    RT.with(|rt| rt.borrow_mut().handle_alloc(ptr as usize));
+/// Protects (`ptr`, `tag`) until the returned guard is dropped, typically
+/// around a call that receives the pointer:
+/// `let _guard = protect(buf, tag); c_fill(buf);`
+/// Under a non-fatal Policy a rejected pointer gets a guard that does nothing.
+pub fn protect<T>(ptr: *const T, tag: Tag) -> Protector {
+    enforce(Protector { tag: Tag::INVALID }, |rt| rt.handle_protect(ptr as usize, tag).map(|()| Protector { tag }))
 }
 
-pub fn track_borrow<T>(parent: *const T, derived: *const T, perm: Perm) {
-    RT.with(|rt| rt.borrow_mut().handle_reborrow(parent as usize, derived as usize, perm));
+/// Returns `Tag::INVALID` if the reborrow is rejected under a non-fatal Policy.
+pub fn track_borrow<T>(parent: *const T, parent_tag: Tag, perm: Perm) -> Tag {
+    enforce(Tag::INVALID, |rt| rt.handle_reborrow(parent as usize, parent_tag, perm))
 }
 
-pub fn check_access<T>(ptr: *const T) {
-    RT.with(|rt| rt.borrow_mut().handle_access(ptr as usize));
-}
\ No newline at end of file
+/// Like `track_borrow`, releasing the borrow when the guard is dropped.
+pub fn track_borrow_scoped<T>(parent: *const T, parent_tag: Tag, perm: Perm) -> ScopedBorrow {
+    ScopedBorrow { addr: parent as usize, tag: track_borrow(parent, parent_tag, perm) }
+}
+
+pub fn try_track_release<T>(ptr: *const T, tag: Tag) -> Result<(), Violation> {
+    with_runtime(|rt| rt.handle_release(ptr as usize, tag))
+}
+
+/// Ends a borrow created with `track_borrow` (see `Runtime::handle_release`).
+pub fn track_release<T>(ptr: *const T, tag: Tag) {
+    enforce((), |rt| rt.handle_release(ptr as usize, tag))
+}
+
+/// Tracks a `realloc` from `old` to `new` (see `Runtime::handle_realloc`).
+/// Returns `Tag::INVALID` if it is rejected under a non-fatal Policy.
+pub fn track_realloc<T, U>(old: *const T, new: *const U, new_size: usize) -> Tag {
+    enforce(Tag::INVALID, |rt| rt.handle_realloc(old as usize, new as usize, new_size))
+}
+
+pub fn track_free<T>(ptr: *const T) {
+    enforce((), |rt| rt.handle_free(ptr as usize))
+}
+
+/// Validates a read of the `T` at `ptr`: all `size_of::<T>()` bytes must
+/// lie inside the allocation `tag` was derived from.
+pub fn check_read<T>(ptr: *const T, tag: Tag) {
+    check_read_bytes(ptr, std::mem::size_of::<T>(), tag)
+}
+
+/// Validates a write of the `T` at `ptr` (see `check_read`).
+pub fn check_write<T>(ptr: *const T, tag: Tag) {
+    check_write_bytes(ptr, std::mem::size_of::<T>(), tag)
+}
+
+/// Like `check_read`, for accesses of `len` bytes (slices, buffers).
+pub fn check_read_bytes<T>(ptr: *const T, len: usize, tag: Tag) {
+    enforce((), |rt| rt.handle_read(ptr as usize, len, tag))
+}
+
+/// Like `check_write`, for accesses of `len` bytes (slices, buffers).
+pub fn check_write_bytes<T>(ptr: *const T, len: usize, tag: Tag) {
+    enforce((), |rt| rt.handle_write(ptr as usize, len, tag))
+}
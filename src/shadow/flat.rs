@@ -0,0 +1,196 @@
+//! Flat shadow: one table entry per address granule, as in AddressSanitizer.
+
+use std::io;
+use std::os::raw::{c_int, c_long, c_void};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+use super::{RangeShadow, ShadowIndex};
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long) -> *mut c_void;
+}
+
+const PROT_READ: c_int = 0x1;
+const PROT_WRITE: c_int = 0x2;
+const MAP_PRIVATE: c_int = 0x02;
+#[cfg(target_os = "linux")]
+const MAP_ANONYMOUS: c_int = 0x20;
+#[cfg(target_os = "linux")]
+const MAP_NORESERVE: c_int = 0x4000;
+#[cfg(target_os = "macos")]
+const MAP_ANONYMOUS: c_int = 0x1000;
+#[cfg(target_os = "macos")]
+const MAP_NORESERVE: c_int = 0x40;
+
+/// log2 of the granule size: 16 bytes, the alignment of `malloc` on 64-bit targets.
+const GRANULE_SHIFT: u32 = 4;
+/// Addresses covered by the table: the user half of a 48-bit address space.
+const ADDRESS_BITS: u32 = 47;
+const ENTRIES: usize = 1 << (ADDRESS_BITS - GRANULE_SHIFT);
+
+/// A table with one `u32` per 16-byte granule of the address space, holding
+/// the slot of the allocation that owns it plus one (0 = untracked).
+/// Resolving an address costs a shift and a load.
+///
+/// The table is reserved once per process (`MAP_NORESERVE`) and shared by
+/// every `FlatShadow`, e.g. one per thread-local runtime. Only the pages
+/// holding touched entries are ever backed by memory, so its footprint
+/// grows with the tracked address ranges rather than their number.
+///
+/// The table is only a hint, backed by a `RangeShadow` that stays exact.
+/// A granule has a single owner: when two allocations share one (neither
+/// is 16-byte aligned, or they belong to different runtimes), the later
+/// one claims it, and addresses of the earlier one in that granule are
+/// resolved through the ranges instead. Likewise, unmapping leaves the
+/// entries in place, to be corrected by the next allocation there.
+/// Addresses outside the lower 2^47 bytes, which aarch64 and 5-level
+/// paging hand out, are resolved through the ranges alone.
+pub struct FlatShadow {
+    table: &'static [AtomicU32],
+    ranges: RangeShadow,
+}
+
+/// The process-wide table, or the OS error its reservation failed with.
+static TABLE: OnceLock<Result<Table, i32>> = OnceLock::new();
+
+struct Table(*const AtomicU32);
+
+// The table is never unmapped, and its entries are atomics.
+unsafe impl Send for Table {}
+unsafe impl Sync for Table {}
+
+impl FlatShadow {
+    /// Uses the process-wide table, reserving it on first use. Fails if the
+    /// address space cannot be reserved.
+    pub fn new() -> io::Result<Self> {
+        let table = TABLE.get_or_init(|| {
+            let table = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    ENTRIES * std::mem::size_of::<u32>(),
+                    PROT_READ | PROT_WRITE,
+                    MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE,
+                    -1,
+                    0,
+                )
+            };
+            if table as isize == -1 {
+                return Err(io::Error::last_os_error().raw_os_error().unwrap_or(0));
+            }
+            Ok(Table(table.cast()))
+        });
+        let table = match table {
+            // SAFETY: the mapping holds `ENTRIES` zeroed entries and is never unmapped.
+            Ok(Table(table)) => unsafe { std::slice::from_raw_parts(*table, ENTRIES) },
+            Err(code) => return Err(io::Error::from_raw_os_error(*code)),
+        };
+        Ok(Self { table, ranges: RangeShadow::new() })
+    }
+
+    /// The entries of the granules `[base, end)` touches, clipped to the table.
+    fn entries(&self, base: usize, end: usize) -> &[AtomicU32] {
+        let first = (base >> GRANULE_SHIFT).min(ENTRIES);
+        let last = ((end - 1) >> GRANULE_SHIFT).saturating_add(1).min(ENTRIES);
+        &self.table[first..last]
+    }
+}
+
+impl ShadowIndex for FlatShadow {
+    fn name(&self) -> &'static str {
+        "flat"
+    }
+
+    fn map(&mut self, base: usize, end: usize, slot: u32) {
+        for entry in self.entries(base, end) {
+            entry.store(slot + 1, Ordering::Relaxed);
+        }
+        self.ranges.map(base, end, slot);
+    }
+
+    fn unmap(&mut self, base: usize, end: usize, slot: u32) {
+        self.ranges.unmap(base, end, slot);
+    }
+
+    fn lookup(&self, addr: usize) -> Option<u32> {
+        match self.table.get(addr >> GRANULE_SHIFT) {
+            // Untouched pages read as zero.
+            Some(entry) => entry.load(Ordering::Relaxed).checked_sub(1),
+            None => self.ranges.lookup(addr),
+        }
+    }
+
+    fn resolve(&self, addr: usize) -> Option<u32> {
+        self.ranges.lookup(addr)
+    }
+
+    fn overlapping(&self, base: usize, end: usize) -> Vec<u32> {
+        self.ranges.overlapping(base, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::runtime::Runtime;
+    use crate::shadow::Shadow;
+    use crate::violation::Violation;
+
+    // The table is shared by every test, so each one uses its own addresses.
+
+    #[test]
+    fn addresses_above_the_table_resolve_through_ranges() {
+        let base = (1 << ADDRESS_BITS) + 0x1000;
+        let mut flat = FlatShadow::new().unwrap();
+        flat.map(base, base + 64, 3);
+        assert_eq!(flat.lookup(base + 8), Some(3));
+        assert_eq!(flat.lookup(base + 64), None);
+
+        let mut rt = Runtime::with_shadow(Model::TreeBorrows, Shadow::Flat);
+        let owner = rt.handle_alloc(base, 64);
+        rt.handle_write(base + 8, 8, owner).unwrap();
+        rt.handle_free(base).unwrap();
+        assert!(matches!(rt.handle_free(base), Err(Violation::DoubleFree { .. })));
+        assert!(matches!(rt.handle_read(base, 4, owner), Err(Violation::UseAfterFree { .. })));
+    }
+
+    #[test]
+    fn shared_granule_resolves_the_earlier_owner_through_ranges() {
+        let base = 0x7100_0000_1000;
+        let mut flat = FlatShadow::new().unwrap();
+        flat.map(base, base + 8, 0);
+        flat.map(base + 8, base + 24, 1);
+        // The later allocation claimed the granule.
+        assert_eq!(flat.lookup(base), Some(1));
+        assert_eq!(flat.resolve(base), Some(0));
+        assert_eq!(flat.resolve(base + 8), Some(1));
+
+        let mut rt = Runtime::with_shadow(Model::TreeBorrows, Shadow::Flat);
+        let first = rt.handle_alloc(base + 0x100, 8);
+        let second = rt.handle_alloc(base + 0x108, 16);
+        rt.handle_write(base + 0x100, 8, first).unwrap();
+        rt.handle_write(base + 0x108, 8, second).unwrap();
+        assert_eq!(rt.owner_tag(base + 0x104), Some(first));
+        assert_eq!(rt.owner_tag(base + 0x10c), Some(second));
+    }
+
+    #[test]
+    fn stale_entry_after_unmap_is_not_trusted() {
+        let base = 0x7200_0000_1000;
+        let mut flat = FlatShadow::new().unwrap();
+        flat.map(base, base + 64, 2);
+        flat.unmap(base, base + 64, 2);
+        assert_eq!(flat.lookup(base), Some(2));
+        assert_eq!(flat.resolve(base), None);
+
+        let mut rt = Runtime::with_shadow(Model::TreeBorrows, Shadow::Flat);
+        let old = rt.handle_alloc(base + 0x100, 64);
+        rt.handle_free(base + 0x100).unwrap();
+        // Evicts the freed region and takes its slot, but not its first granules.
+        let new = rt.handle_alloc(base + 0x120, 16);
+        assert!(matches!(rt.handle_read(base + 0x100, 4, old), Err(Violation::UseAfterFree { .. })));
+        assert!(rt.owner_tag(base + 0x100).is_none());
+        assert_eq!(rt.owner_tag(base + 0x120), Some(new));
+    }
+}
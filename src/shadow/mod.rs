@@ -0,0 +1,97 @@
+//! Shadow backends: how the Runtime resolves an address to the allocation
+//! covering it. The Runtime keeps the allocations themselves in slots and
+//! asks a `ShadowIndex` which slot an address belongs to, so the lookup
+//! structure can be chosen per workload.
+
+// The flat table needs a 64-bit address space and `mmap` with `MAP_NORESERVE`.
+#[cfg(all(target_pointer_width = "64", any(target_os = "linux", target_os = "macos")))]
+mod flat;
+mod ranges;
+
+#[cfg(all(target_pointer_width = "64", any(target_os = "linux", target_os = "macos")))]
+pub use flat::FlatShadow;
+pub use ranges::RangeShadow;
+
+use crate::env::{self, EnvSetting};
+
+/// Maps address ranges to the slots of the allocations covering them.
+/// Ranges are half-open (`[base, end)`) and never empty.
+pub trait ShadowIndex: Send {
+    /// Human-readable name, for reports and comparisons.
+    fn name(&self) -> &'static str;
+
+    /// Records that `[base, end)` belongs to `slot`.
+    fn map(&mut self, base: usize, end: usize, slot: u32);
+
+    /// Forgets `[base, end)`, wherever it still belongs to `slot`.
+    fn unmap(&mut self, base: usize, end: usize, slot: u32);
+
+    /// The slot recorded for `addr`, if any. The caller still checks that
+    /// the allocation in that slot contains `addr`, and calls `resolve` if
+    /// it does not. None means no recorded range contains `addr`.
+    fn lookup(&self, addr: usize) -> Option<u32>;
+
+    /// The slot whose range contains `addr`, for when the slot `lookup`
+    /// returned does not.
+    fn resolve(&self, addr: usize) -> Option<u32> {
+        self.lookup(addr)
+    }
+
+    /// The slots whose ranges overlap `[base, end)`; may repeat a slot.
+    fn overlapping(&self, base: usize, end: usize) -> Vec<u32>;
+}
+
+/// Selects the shadow backend of a Runtime.
+/// Read from the `CAPSLOCK_SHADOW` environment variable (`ranges` or `flat`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shadow {
+    /// `RangeShadow` (default): an ordered map of allocation ranges.
+    Ranges,
+    /// `FlatShadow`: a flat table indexed by address granule. Only
+    /// available on 64-bit Linux and macOS; `Ranges` is used elsewhere.
+    Flat,
+}
+
+impl EnvSetting for Shadow {
+    const ENV_VAR: &'static str = "CAPSLOCK_SHADOW";
+    const DEFAULT: Self = Shadow::Ranges;
+    const VALUES: &'static [(&'static str, Self)] = &[("ranges", Shadow::Ranges), ("flat", Shadow::Flat)];
+}
+
+impl Shadow {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shadow::Ranges => "ranges",
+            Shadow::Flat => "flat",
+        }
+    }
+
+    /// Creates an empty index. Falls back to `RangeShadow` if the flat
+    /// table cannot be reserved, or is not supported on this target.
+    pub fn build(self) -> Box<dyn ShadowIndex> {
+        match self {
+            Shadow::Ranges => Box::new(RangeShadow::new()),
+            #[cfg(all(target_pointer_width = "64", any(target_os = "linux", target_os = "macos")))]
+            Shadow::Flat => match FlatShadow::new() {
+                Ok(flat) => Box::new(flat),
+                Err(err) => {
+                    eprintln!("[CapsLock] Flat shadow unavailable ({}), using ranges", err);
+                    Box::new(RangeShadow::new())
+                }
+            },
+            #[cfg(not(all(target_pointer_width = "64", any(target_os = "linux", target_os = "macos"))))]
+            Shadow::Flat => {
+                eprintln!("[CapsLock] Flat shadow unsupported on this target, using ranges");
+                Box::new(RangeShadow::new())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Shadow {
+    type Err = String;
+
+    fn from_str(shadow: &str) -> Result<Self, Self::Err> {
+        env::parse(shadow)
+    }
+}
@@ -0,0 +1,59 @@
+//! Range shadow: an ordered map from base addresses to allocation ranges.
+
+use std::collections::BTreeMap;
+
+use super::ShadowIndex;
+
+/// Interval map keyed by base address, so any interior pointer resolves to
+/// its allocation through the closest base at or below it. Lookups cost
+/// O(log n) in the number of allocations, and memory is proportional to
+/// them, which suits small programs.
+pub struct RangeShadow {
+    /// base -> (end, slot). Ranges are disjoint.
+    ranges: BTreeMap<usize, (usize, u32)>,
+}
+
+impl Default for RangeShadow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RangeShadow {
+    pub fn new() -> Self {
+        Self { ranges: BTreeMap::new() }
+    }
+}
+
+impl ShadowIndex for RangeShadow {
+    fn name(&self) -> &'static str {
+        "ranges"
+    }
+
+    fn map(&mut self, base: usize, end: usize, slot: u32) {
+        self.ranges.insert(base, (end, slot));
+    }
+
+    fn unmap(&mut self, base: usize, _end: usize, slot: u32) {
+        if self.ranges.get(&base).is_some_and(|&(_, owner)| owner == slot) {
+            self.ranges.remove(&base);
+        }
+    }
+
+    fn lookup(&self, addr: usize) -> Option<u32> {
+        self.ranges
+            .range(..=addr)
+            .next_back()
+            .filter(|(_, &(end, _))| addr < end)
+            .map(|(_, &(_, slot))| slot)
+    }
+
+    fn overlapping(&self, base: usize, end: usize) -> Vec<u32> {
+        self.ranges
+            .range(..end)
+            .rev()
+            .take_while(|(_, &(e, _))| e > base)
+            .map(|(_, &(_, slot))| slot)
+            .collect()
+    }
+}
@@ -0,0 +1,213 @@
+//! Structured reports for the violations detected by the runtime monitor.
+
+use std::fmt;
+
+use crate::env::{self, EnvSetting};
+use crate::runtime::{Perm, Tag};
+
+/// A provenance or aliasing violation.
+/// `lineage` lists the offending node and its ancestors, innermost first,
+/// ending at the allocation root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Access through a pointer whose Tag (or an ancestor) was revoked (Disabled).
+    /// `perm` is None once the model has forgotten the node.
+    UseAfterRevoke { addr: usize, tag: Tag, perm: Option<Perm>, lineage: Vec<Tag> },
+    /// Write through a pointer that is read-only (Frozen): a Shared borrow,
+    /// a borrow derived from one, or a Mutable borrow frozen by a foreign read.
+    WriteThroughShared { addr: usize, tag: Tag, lineage: Vec<Tag> },
+    /// Reborrow from a pointer whose Tag (or an ancestor) was revoked.
+    ReborrowFromInvalid { addr: usize, tag: Tag, perm: Option<Perm>, lineage: Vec<Tag> },
+    /// Reborrow from an address that belongs to no tracked allocation.
+    UntrackedReborrow { addr: usize, tag: Tag },
+    /// The Tag was not derived from the allocation containing the address.
+    ForeignTag { addr: usize, tag: Tag, lineage: Vec<Tag> },
+    /// Access or reborrow through a Tag whose allocation was freed,
+    /// whether or not the address has been reused since.
+    UseAfterFree { addr: usize, tag: Tag, lineage: Vec<Tag> },
+    /// Access or reborrow through a Tag of an allocation whose address range
+    /// was since claimed by a newer allocation (a stale generation, ABA).
+    StaleAllocation { addr: usize, tag: Tag, lineage: Vec<Tag> },
+    /// The allocation at this address was already freed.
+    DoubleFree { addr: usize },
+    /// Free or realloc of `addr`, inside the allocation at `base` rather
+    /// than at its start.
+    InvalidFree { addr: usize, base: usize },
+    /// Access or reborrow through `tag` that would invalidate `protected`, a
+    /// pointer protected for the duration of a call, or make it read-only.
+    ProtectedRevoke { addr: usize, tag: Tag, protected: Tag, lineage: Vec<Tag> },
+    /// Reborrow of `tag` (`parent_perm`) with a wider permission `perm`,
+    /// e.g. a `&mut T` cast from a `&T`. Nothing is derived.
+    PermissionEscalation { addr: usize, tag: Tag, parent_perm: Perm, perm: Perm, lineage: Vec<Tag> },
+    /// Access of `len` bytes at `addr` that leaves `[base, base + size)`,
+    /// the live allocation `tag` was derived from.
+    OutOfBounds { addr: usize, len: usize, tag: Tag, base: usize, size: usize, lineage: Vec<Tag> },
+}
+
+impl Violation {
+    /// The address at which the violation was detected.
+    pub fn addr(&self) -> usize {
+        match self {
+            Violation::UseAfterRevoke { addr, .. }
+            | Violation::WriteThroughShared { addr, .. }
+            | Violation::ReborrowFromInvalid { addr, .. }
+            | Violation::UntrackedReborrow { addr, .. }
+            | Violation::ForeignTag { addr, .. }
+            | Violation::UseAfterFree { addr, .. }
+            | Violation::StaleAllocation { addr, .. }
+            | Violation::DoubleFree { addr }
+            | Violation::InvalidFree { addr, .. }
+            | Violation::ProtectedRevoke { addr, .. }
+            | Violation::PermissionEscalation { addr, .. }
+            | Violation::OutOfBounds { addr, .. } => *addr,
+        }
+    }
+
+    /// The Tag of the offending pointer, if one was involved.
+    pub fn tag(&self) -> Option<Tag> {
+        match self {
+            Violation::UseAfterRevoke { tag, .. }
+            | Violation::WriteThroughShared { tag, .. }
+            | Violation::ReborrowFromInvalid { tag, .. }
+            | Violation::UntrackedReborrow { tag, .. }
+            | Violation::ForeignTag { tag, .. }
+            | Violation::UseAfterFree { tag, .. }
+            | Violation::StaleAllocation { tag, .. }
+            | Violation::ProtectedRevoke { tag, .. }
+            | Violation::PermissionEscalation { tag, .. }
+            | Violation::OutOfBounds { tag, .. } => Some(*tag),
+            Violation::DoubleFree { .. } | Violation::InvalidFree { .. } => None,
+        }
+    }
+
+    /// The offending node's lineage (empty if the Tag is unknown to the model).
+    pub fn lineage(&self) -> &[Tag] {
+        match self {
+            Violation::UseAfterRevoke { lineage, .. }
+            | Violation::WriteThroughShared { lineage, .. }
+            | Violation::ReborrowFromInvalid { lineage, .. }
+            | Violation::ForeignTag { lineage, .. }
+            | Violation::UseAfterFree { lineage, .. }
+            | Violation::StaleAllocation { lineage, .. }
+            | Violation::ProtectedRevoke { lineage, .. }
+            | Violation::PermissionEscalation { lineage, .. }
+            | Violation::OutOfBounds { lineage, .. } => lineage,
+            Violation::UntrackedReborrow { .. } | Violation::DoubleFree { .. } | Violation::InvalidFree { .. } => &[],
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::UseAfterRevoke { addr, tag, perm: Some(perm), .. } => write!(
+                f,
+                "[Security Violation] Use-After-Free/Revocation at 0x{:x} ({:?}, {:?})",
+                addr, tag, perm
+            ),
+            Violation::UseAfterRevoke { addr, tag, perm: None, .. } => write!(
+                f,
+                "[Security Violation] Use-After-Free/Revocation at 0x{:x} ({:?})",
+                addr, tag
+            ),
+            Violation::WriteThroughShared { addr, tag, .. } => write!(
+                f,
+                "[Security Violation] Write through Shared pointer at 0x{:x} ({:?})",
+                addr, tag
+            ),
+            Violation::ReborrowFromInvalid { addr, tag, perm: Some(perm), .. } => write!(
+                f,
+                "[Security] Parent {:?} ({:?}) at 0x{:x} is already invalidated.",
+                tag, perm, addr
+            ),
+            Violation::ReborrowFromInvalid { addr, tag, perm: None, .. } => write!(
+                f,
+                "[Security] Parent {:?} at 0x{:x} is already invalidated.",
+                tag, addr
+            ),
+            Violation::UntrackedReborrow { addr, .. } => {
+                write!(f, "[Security] Reborrow from untracked address 0x{:x}", addr)
+            }
+            Violation::ForeignTag { addr, tag, .. } => write!(
+                f,
+                "[Security Violation] Access at 0x{:x} uses {:?} from another allocation",
+                addr, tag
+            ),
+            Violation::UseAfterFree { addr, tag, .. } => write!(
+                f,
+                "[Security Violation] Use-After-Free at 0x{:x} ({:?})",
+                addr, tag
+            ),
+            Violation::StaleAllocation { addr, tag, .. } => write!(
+                f,
+                "[Security Violation] Stale pointer at 0x{:x}: allocation #{} was replaced ({:?})",
+                addr,
+                tag.alloc_id(),
+                tag
+            ),
+            Violation::DoubleFree { addr } => {
+                write!(f, "[Security Violation] Double free at 0x{:x}", addr)
+            }
+            Violation::InvalidFree { addr, base } => write!(
+                f,
+                "[Security Violation] Invalid free at 0x{:x}: inside the allocation at 0x{:x}",
+                addr, base
+            ),
+            Violation::ProtectedRevoke { addr, tag, protected, .. } => write!(
+                f,
+                "[Security Violation] Access at 0x{:x} through {:?} would invalidate protected {:?}",
+                addr, tag, protected
+            ),
+            Violation::PermissionEscalation { addr, tag, parent_perm, perm, .. } => write!(
+                f,
+                "[Security Violation] Permission escalation at 0x{:x}: {:?} derived from {:?} ({:?})",
+                addr, perm, tag, parent_perm
+            ),
+            Violation::OutOfBounds { addr, len, tag, base, size, .. } => write!(
+                f,
+                "[Security Violation] Out-of-bounds access of {} bytes at 0x{:x} ({:?}): allocation is 0x{:x}..0x{:x}",
+                len,
+                addr,
+                tag,
+                base,
+                base + size
+            ),
+        }?;
+
+        if self.lineage().len() > 1 {
+            write!(f, " lineage: {:?}", self.lineage())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// What the runtime does when an enforcing entry point detects a violation.
+/// Selected with `set_policy` or the `CAPSLOCK_MODE` environment variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Panic with the violation report (default; what CI wants).
+    Panic,
+    /// Print the report and `abort()` the process.
+    Abort,
+    /// Print the report and continue.
+    Log,
+    /// Continue silently; the violation is only counted.
+    Count,
+}
+
+impl EnvSetting for Policy {
+    const ENV_VAR: &'static str = "CAPSLOCK_MODE";
+    const DEFAULT: Self = Policy::Panic;
+    const VALUES: &'static [(&'static str, Self)] =
+        &[("panic", Policy::Panic), ("abort", Policy::Abort), ("log", Policy::Log), ("count", Policy::Count)];
+}
+
+impl std::str::FromStr for Policy {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        env::parse(mode)
+    }
+}